@@ -0,0 +1,11 @@
+-- Per-user daily routine captured at signup
+CREATE TABLE IF NOT EXISTS public.routines (
+    user_id uuid NOT NULL,
+    work_start_time time without time zone NOT NULL,
+    work_end_time time without time zone NOT NULL,
+    sleep_time time without time zone NOT NULL,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    CONSTRAINT routines_pkey PRIMARY KEY (user_id),
+    CONSTRAINT routines_user_id_fkey FOREIGN KEY (user_id) REFERENCES public.users(id) ON DELETE CASCADE
+);
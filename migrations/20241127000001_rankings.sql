@@ -0,0 +1,11 @@
+-- Latest ranking of preferences/categories returned by the external API for each user
+CREATE TABLE IF NOT EXISTS public.rankings (
+    user_id uuid NOT NULL,
+    ranked_preferences jsonb NOT NULL,
+    ranked_categories jsonb NOT NULL,
+    ranked_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    CONSTRAINT rankings_pkey PRIMARY KEY (user_id),
+    CONSTRAINT rankings_user_id_fkey FOREIGN KEY (user_id) REFERENCES public.users(id) ON DELETE CASCADE
+);
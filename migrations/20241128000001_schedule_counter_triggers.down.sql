@@ -0,0 +1,67 @@
+DROP TRIGGER IF EXISTS tasks_sync_schedule_counters ON public.tasks;
+DROP TRIGGER IF EXISTS tasks_ensure_schedule ON public.tasks;
+DROP FUNCTION IF EXISTS public.sync_schedule_counters();
+DROP FUNCTION IF EXISTS public.ensure_task_schedule();
+DROP FUNCTION IF EXISTS public.refresh_schedule_counters(uuid, date);
+
+-- Restore import_schedule with its own counting logic
+CREATE OR REPLACE FUNCTION public.import_schedule(p_numeric_id integer, p_date date, p_tasks jsonb) RETURNS void
+    LANGUAGE plpgsql
+    AS $$
+  DECLARE
+      v_user_uuid uuid;
+      v_task JSONB;
+      v_completed_count INTEGER := 0;
+      v_total_count INTEGER;
+  BEGIN
+      -- Get the UUID for the numeric ID
+      SELECT id INTO v_user_uuid
+      FROM users u
+      INNER JOIN profiles p ON p.user_id = u.id
+      WHERE p.user_id IN (
+        SELECT uuid
+        FROM id_mapping
+        WHERE numeric_id = p_numeric_id
+      );
+
+      IF v_user_uuid IS NULL THEN
+          RAISE EXCEPTION 'No UUID found for numeric_id %', p_numeric_id;
+      END IF;
+
+      -- Count total and completed tasks
+      SELECT count(*), count(*) FILTER (WHERE (value->>'completed')::boolean)
+      INTO v_total_count, v_completed_count
+      FROM jsonb_array_elements(p_tasks);
+
+      -- Insert schedule
+      INSERT INTO schedules (user_id, schedule_date, completed_tasks, total_tasks)
+      VALUES (v_user_uuid, p_date, v_completed_count, v_total_count)
+      ON CONFLICT (user_id, schedule_date) DO UPDATE SET
+          completed_tasks = EXCLUDED.completed_tasks,
+          total_tasks = EXCLUDED.total_tasks,
+          updated_at = CURRENT_TIMESTAMP;
+
+      -- Insert tasks
+      FOR v_task IN SELECT value FROM jsonb_array_elements(p_tasks)
+      LOOP
+          INSERT INTO tasks (
+              user_id,
+              schedule_date,
+              name,
+              category,
+              start_time,
+              end_time,
+              completed
+          )
+          VALUES (
+              v_user_uuid,
+              p_date,
+              v_task->>'name',
+              v_task->>'category',
+              (p_date || ' ' || (v_task->>'startTime'))::TIMESTAMP WITH TIME ZONE,
+              (p_date || ' ' || (v_task->>'endTime'))::TIMESTAMP WITH TIME ZONE,
+              (v_task->>'completed')::boolean
+          );
+      END LOOP;
+  END;
+  $$;
@@ -0,0 +1,137 @@
+-- Maintain schedules.total_tasks / completed_tasks from tasks, so every write path
+-- (API, bulk imports, manual SQL) keeps the counters consistent
+
+-- Recount a single schedule from its tasks, returning whether the counters changed
+--
+-- Under READ COMMITTED two transactions adding tasks to the same day could each count
+-- without the other's row, so the schedule row is locked before counting
+CREATE OR REPLACE FUNCTION public.refresh_schedule_counters(p_user_id uuid, p_date date) RETURNS boolean
+    LANGUAGE plpgsql
+    AS $$
+BEGIN
+    PERFORM 1 FROM schedules
+    WHERE user_id = p_user_id AND schedule_date = p_date
+    FOR UPDATE;
+
+    -- A new statement, so it sees tasks committed while waiting for the lock
+    UPDATE schedules s
+    SET total_tasks = c.total_tasks,
+        completed_tasks = c.completed_tasks,
+        updated_at = CURRENT_TIMESTAMP
+    FROM (
+        SELECT count(*)::int AS total_tasks,
+               count(*) FILTER (WHERE completed)::int AS completed_tasks
+        FROM tasks
+        WHERE user_id = p_user_id AND schedule_date = p_date
+    ) c
+    WHERE s.user_id = p_user_id
+      AND s.schedule_date = p_date
+      AND (s.total_tasks, s.completed_tasks) IS DISTINCT FROM (c.total_tasks, c.completed_tasks);
+
+    RETURN FOUND;
+END;
+$$;
+
+-- Tasks reference their schedule, so create it before the row is written
+CREATE OR REPLACE FUNCTION public.ensure_task_schedule() RETURNS trigger
+    LANGUAGE plpgsql
+    AS $$
+BEGIN
+    INSERT INTO schedules (user_id, schedule_date)
+    VALUES (NEW.user_id, NEW.schedule_date)
+    ON CONFLICT (user_id, schedule_date) DO NOTHING;
+    RETURN NEW;
+END;
+$$;
+
+CREATE OR REPLACE FUNCTION public.sync_schedule_counters() RETURNS trigger
+    LANGUAGE plpgsql
+    AS $$
+DECLARE
+    v_moved boolean := TG_OP = 'UPDATE'
+        AND (NEW.user_id, NEW.schedule_date) IS DISTINCT FROM (OLD.user_id, OLD.schedule_date);
+BEGIN
+    -- Lock both days in a fixed order, so opposite moves can't deadlock
+    IF v_moved THEN
+        PERFORM 1 FROM schedules
+        WHERE (user_id, schedule_date) IN ((OLD.user_id, OLD.schedule_date), (NEW.user_id, NEW.schedule_date))
+        ORDER BY user_id, schedule_date
+        FOR UPDATE;
+    END IF;
+
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        PERFORM refresh_schedule_counters(OLD.user_id, OLD.schedule_date);
+    END IF;
+
+    IF TG_OP = 'INSERT' OR v_moved THEN
+        PERFORM refresh_schedule_counters(NEW.user_id, NEW.schedule_date);
+    END IF;
+
+    RETURN NULL;
+END;
+$$;
+
+DROP TRIGGER IF EXISTS tasks_ensure_schedule ON public.tasks;
+CREATE TRIGGER tasks_ensure_schedule
+    BEFORE INSERT OR UPDATE OF user_id, schedule_date ON public.tasks
+    FOR EACH ROW EXECUTE FUNCTION public.ensure_task_schedule();
+
+DROP TRIGGER IF EXISTS tasks_sync_schedule_counters ON public.tasks;
+CREATE TRIGGER tasks_sync_schedule_counters
+    AFTER INSERT OR UPDATE OF user_id, schedule_date, completed OR DELETE ON public.tasks
+    FOR EACH ROW EXECUTE FUNCTION public.sync_schedule_counters();
+
+-- Counters are now derived from tasks, so imports only need to ensure the schedule exists
+CREATE OR REPLACE FUNCTION public.import_schedule(p_numeric_id integer, p_date date, p_tasks jsonb) RETURNS void
+    LANGUAGE plpgsql
+    AS $$
+  DECLARE
+      v_user_uuid uuid;
+      v_task JSONB;
+  BEGIN
+      -- Get the UUID for the numeric ID
+      SELECT id INTO v_user_uuid
+      FROM users u
+      INNER JOIN profiles p ON p.user_id = u.id
+      WHERE p.user_id IN (
+        SELECT uuid
+        FROM id_mapping
+        WHERE numeric_id = p_numeric_id
+      );
+
+      IF v_user_uuid IS NULL THEN
+          RAISE EXCEPTION 'No UUID found for numeric_id %', p_numeric_id;
+      END IF;
+
+      -- Insert schedule (counters are maintained by the tasks triggers)
+      INSERT INTO schedules (user_id, schedule_date)
+      VALUES (v_user_uuid, p_date)
+      ON CONFLICT (user_id, schedule_date) DO NOTHING;
+
+      -- Insert tasks
+      FOR v_task IN SELECT value FROM jsonb_array_elements(p_tasks)
+      LOOP
+          INSERT INTO tasks (
+              user_id,
+              schedule_date,
+              name,
+              category,
+              start_time,
+              end_time,
+              completed
+          )
+          VALUES (
+              v_user_uuid,
+              p_date,
+              v_task->>'name',
+              v_task->>'category',
+              (p_date || ' ' || (v_task->>'startTime'))::TIMESTAMP WITH TIME ZONE,
+              (p_date || ' ' || (v_task->>'endTime'))::TIMESTAMP WITH TIME ZONE,
+              (v_task->>'completed')::boolean
+          );
+      END LOOP;
+  END;
+  $$;
+
+-- Repair any counters that drifted before the triggers existed
+SELECT refresh_schedule_counters(user_id, schedule_date) FROM schedules;
@@ -0,0 +1,2 @@
+-- IANA timezone (e.g. Europe/Berlin) used to interpret a user's task times and "today"
+ALTER TABLE public.profiles ADD COLUMN IF NOT EXISTS timezone text DEFAULT 'UTC' NOT NULL;
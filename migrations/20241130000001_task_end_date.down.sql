@@ -0,0 +1,5 @@
+DROP TRIGGER IF EXISTS tasks_default_end_date ON public.tasks;
+DROP FUNCTION IF EXISTS public.default_task_end_date();
+DROP INDEX IF EXISTS public.idx_tasks_user_end_date;
+ALTER TABLE public.tasks DROP CONSTRAINT IF EXISTS tasks_end_date_check;
+ALTER TABLE public.tasks DROP COLUMN IF EXISTS end_date;
@@ -0,0 +1,27 @@
+-- Tasks may run past midnight: schedule_date is the (counted) start day, end_date the
+-- last day the task occupies. Both are dates in the user's timezone
+ALTER TABLE public.tasks ADD COLUMN IF NOT EXISTS end_date date;
+
+UPDATE public.tasks SET end_date = schedule_date WHERE end_date IS NULL;
+
+ALTER TABLE public.tasks ALTER COLUMN end_date SET NOT NULL;
+
+ALTER TABLE public.tasks
+    ADD CONSTRAINT tasks_end_date_check CHECK (end_date >= schedule_date);
+
+CREATE INDEX IF NOT EXISTS idx_tasks_user_end_date ON public.tasks(user_id, end_date);
+
+-- Writers that predate end_date (e.g. import_schedule) create single-day tasks
+CREATE OR REPLACE FUNCTION public.default_task_end_date() RETURNS trigger
+    LANGUAGE plpgsql
+    AS $$
+BEGIN
+    NEW.end_date := COALESCE(NEW.end_date, NEW.schedule_date);
+    RETURN NEW;
+END;
+$$;
+
+DROP TRIGGER IF EXISTS tasks_default_end_date ON public.tasks;
+CREATE TRIGGER tasks_default_end_date
+    BEFORE INSERT ON public.tasks
+    FOR EACH ROW EXECUTE FUNCTION public.default_task_end_date();
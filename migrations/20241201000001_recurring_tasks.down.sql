@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS public.recurring_task_exceptions;
+DROP TABLE IF EXISTS public.recurring_tasks;
@@ -0,0 +1,46 @@
+-- A recurring task is stored once and expanded into occurrences when schedules are read.
+-- start_time is local to the user's timezone; weekdays are ISO (1 = Monday .. 7 = Sunday)
+CREATE TABLE IF NOT EXISTS public.recurring_tasks (
+    id uuid DEFAULT gen_random_uuid() NOT NULL,
+    user_id uuid NOT NULL,
+    name character varying(255) NOT NULL,
+    category character varying(100) NOT NULL,
+    start_time time without time zone NOT NULL,
+    duration_minutes integer NOT NULL,
+    frequency text NOT NULL,
+    interval integer DEFAULT 1 NOT NULL,
+    weekdays smallint[] DEFAULT '{}'::smallint[] NOT NULL,
+    start_date date NOT NULL,
+    until date,
+    count integer,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    CONSTRAINT recurring_tasks_pkey PRIMARY KEY (id),
+    CONSTRAINT recurring_tasks_user_id_fkey FOREIGN KEY (user_id) REFERENCES public.users(id) ON DELETE CASCADE,
+    CONSTRAINT recurring_tasks_frequency_check CHECK (frequency IN ('daily', 'weekly')),
+    CONSTRAINT recurring_tasks_interval_check CHECK (interval >= 1),
+    CONSTRAINT recurring_tasks_duration_check CHECK (duration_minutes BETWEEN 1 AND 1440),
+    CONSTRAINT recurring_tasks_until_check CHECK (until IS NULL OR until >= start_date),
+    CONSTRAINT recurring_tasks_count_check CHECK (count IS NULL OR count >= 0)
+);
+
+CREATE INDEX IF NOT EXISTS idx_recurring_tasks_user ON public.recurring_tasks(user_id, start_date);
+
+-- Per-occurrence state: completion, cancellation and "this occurrence only" edits
+CREATE TABLE IF NOT EXISTS public.recurring_task_exceptions (
+    recurring_task_id uuid NOT NULL,
+    occurrence_date date NOT NULL,
+    name character varying(255),
+    category character varying(100),
+    start_time time without time zone,
+    duration_minutes integer,
+    completed boolean DEFAULT false NOT NULL,
+    cancelled boolean DEFAULT false NOT NULL,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    CONSTRAINT recurring_task_exceptions_pkey PRIMARY KEY (recurring_task_id, occurrence_date),
+    CONSTRAINT recurring_task_exceptions_recurring_task_id_fkey FOREIGN KEY (recurring_task_id)
+        REFERENCES public.recurring_tasks(id) ON DELETE CASCADE,
+    CONSTRAINT recurring_task_exceptions_duration_check
+        CHECK (duration_minutes IS NULL OR duration_minutes BETWEEN 1 AND 1440)
+);
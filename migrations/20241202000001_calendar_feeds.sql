@@ -0,0 +1,9 @@
+-- Secret per-user token for subscribing to the schedule from calendar apps.
+-- Only a SHA-256 hash of the token is stored; rotating replaces the row
+CREATE TABLE IF NOT EXISTS public.calendar_feeds (
+    user_id uuid NOT NULL,
+    token_hash bytea NOT NULL,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    CONSTRAINT calendar_feeds_pkey PRIMARY KEY (user_id),
+    CONSTRAINT calendar_feeds_user_id_fkey FOREIGN KEY (user_id) REFERENCES public.users(id) ON DELETE CASCADE
+);
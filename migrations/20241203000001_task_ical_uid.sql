@@ -0,0 +1,6 @@
+-- UID of the iCalendar event a task was imported from, so re-importing a calendar
+-- doesn't duplicate it
+ALTER TABLE public.tasks ADD COLUMN IF NOT EXISTS ical_uid text;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_tasks_user_ical_uid
+    ON public.tasks(user_id, ical_uid) WHERE ical_uid IS NOT NULL;
@@ -0,0 +1,3 @@
+-- Matches the ordering of GET /v1/tasks, so each page is an index range scan
+CREATE INDEX IF NOT EXISTS idx_tasks_page_order
+    ON public.tasks(user_id, schedule_date, start_time, id);
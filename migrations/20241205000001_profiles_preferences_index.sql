@@ -0,0 +1,2 @@
+-- Supports the preferences_any (&&) and preferences_all (@>) filters of GET /v1/user/profiles
+CREATE INDEX IF NOT EXISTS idx_profiles_preferences ON public.profiles USING gin (preferences);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS public.idx_profiles_updated_at;
+DROP INDEX IF EXISTS public.idx_tasks_updated_at;
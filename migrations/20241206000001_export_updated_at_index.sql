@@ -0,0 +1,3 @@
+-- Incremental exports read rows in updated_at order from a `since` timestamp
+CREATE INDEX IF NOT EXISTS idx_tasks_updated_at ON public.tasks(updated_at, id);
+CREATE INDEX IF NOT EXISTS idx_profiles_updated_at ON public.profiles(updated_at, user_id);
@@ -1,39 +1,83 @@
 use crate::error::{Error, Result};
-use sqlx::postgres::PgConnectOptions;
+use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use std::{env, str::FromStr};
+
+const DEFAULT_PORT: u16 = 5432;
+const DEFAULT_DATABASE: &str = "neondb";
+const DEFAULT_MAX_CONNECTIONS: u32 = 5;
 
 pub struct DatabaseConfig {
-    pub host: String,
-    pub port: u16,
-    pub username: String,
-    pub password: String,
-    pub database_name: String,
+    pub options: PgConnectOptions,
+    pub max_connections: u32,
 }
 
 impl DatabaseConfig {
+    /// Loads the database config from the environment
+    ///
+    /// `DATABASE_URL` takes precedence when set. Otherwise the connection is built from
+    /// `DB_HOST` (required), `DB_PORT`, `DB_NAME`, `DB_SSLMODE` and the `DB_USER` /
+    /// `DB_PASSWORD` credentials, which fall back to `NEON_USER` / `NEON_PASSWORD`.
+    /// `DB_MAX_CONNECTIONS` sets the pool size in both cases.
     pub fn new() -> Result<Self> {
-        let username =
-            std::env::var("NEON_USER").map_err(|_| Error::validation("NEON_USER must be set"))?;
-        let password = std::env::var("NEON_PASSWORD")
-            .map_err(|_| Error::validation("NEON_PASSWORD must be set"))?;
+        let options = match env::var("DATABASE_URL") {
+            Ok(url) => PgConnectOptions::from_str(&url)
+                .map_err(|_| Error::validation("DATABASE_URL is not a valid Postgres URL"))?,
+            Err(_) => Self::options_from_parts()?,
+        };
+
+        let max_connections = parse_var("DB_MAX_CONNECTIONS")?.unwrap_or(DEFAULT_MAX_CONNECTIONS);
+        if max_connections == 0 {
+            return Err(Error::validation("DB_MAX_CONNECTIONS must be at least 1"));
+        }
 
         Ok(Self {
-            host: "ep-super-flower-a27yz7bg-pooler.eu-central-1.aws.neon.tech".to_string(),
-            port: 5432,
-            username,
-            password,
-            database_name: "neondb".to_string(),
+            options,
+            max_connections,
         })
     }
 
     pub fn connection_options(&self) -> PgConnectOptions {
-        PgConnectOptions::new_without_pgpass()
-            .host(&self.host)
-            .port(self.port)
-            .username(&self.username)
-            .password(&self.password)
-            .database(&self.database_name)
-            .ssl_mode(sqlx::postgres::PgSslMode::Require)
+        self.options
+            .clone()
             .statement_cache_capacity(100)
             .application_name("bustleit-lambda")
     }
+
+    fn options_from_parts() -> Result<PgConnectOptions> {
+        // Never guess the host, so a missing setting can't point at production
+        let host = env::var("DB_HOST")
+            .map_err(|_| Error::validation("DB_HOST must be set (or DATABASE_URL)"))?;
+        let username = var_or_legacy("DB_USER", "NEON_USER")?;
+        let password = var_or_legacy("DB_PASSWORD", "NEON_PASSWORD")?;
+
+        let port = parse_var("DB_PORT")?.unwrap_or(DEFAULT_PORT);
+        let database_name = env::var("DB_NAME").unwrap_or_else(|_| DEFAULT_DATABASE.to_string());
+        let ssl_mode = parse_var("DB_SSLMODE")?.unwrap_or(PgSslMode::Require);
+
+        Ok(PgConnectOptions::new_without_pgpass()
+            .host(&host)
+            .port(port)
+            .username(&username)
+            .password(&password)
+            .database(&database_name)
+            .ssl_mode(ssl_mode))
+    }
+}
+
+// Reads a required env var, falling back to its older name
+fn var_or_legacy(name: &str, legacy: &str) -> Result<String> {
+    env::var(name)
+        .or_else(|_| env::var(legacy))
+        .map_err(|_| Error::validation(format!("{name} (or {legacy}) must be set")))
+}
+
+// Reads an optional env var, failing if it is set but cannot be parsed
+fn parse_var<T: FromStr>(name: &str) -> Result<Option<T>> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::validation(format!("{name} has an invalid value: {value}"))),
+        Err(_) => Ok(None),
+    }
 }
@@ -0,0 +1,41 @@
+use sqlx::{
+    migrate::{Migrate, Migrator},
+    PgPool,
+};
+
+use crate::error::{Error, Result};
+
+/// Migrations from the workspace `migrations/` directory, embedded at compile time
+pub static MIGRATOR: Migrator = sqlx::migrate!("../../migrations");
+
+/// Applies every pending migration
+pub async fn run_migrations(pool: &PgPool) -> Result<()> {
+    MIGRATOR.run(pool).await.map_err(Error::from)
+}
+
+/// Reverts every applied migration newer than `target` (0 reverts everything)
+pub async fn undo_migrations(pool: &PgPool, target: i64) -> Result<()> {
+    MIGRATOR.undo(pool, target).await.map_err(Error::from)
+}
+
+/// Returns the versions of all successfully applied migrations, oldest first
+pub async fn applied_versions(pool: &PgPool) -> Result<Vec<i64>> {
+    let mut conn = pool.acquire().await.map_err(Error::from)?;
+    conn.ensure_migrations_table().await.map_err(Error::from)?;
+
+    let mut versions: Vec<i64> = conn
+        .list_applied_migrations()
+        .await
+        .map_err(Error::from)?
+        .into_iter()
+        .map(|m| m.version)
+        .collect();
+    versions.sort_unstable();
+
+    Ok(versions)
+}
+
+/// Returns the latest applied schema version, if any migration has been applied
+pub async fn current_version(pool: &PgPool) -> Result<Option<i64>> {
+    Ok(applied_versions(pool).await?.last().copied())
+}
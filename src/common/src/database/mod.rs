@@ -1,5 +1,7 @@
 mod config;
+pub mod migrations;
 mod pool;
+pub mod tasks;
 
 pub use config::DatabaseConfig;
 pub use pool::create_pool;
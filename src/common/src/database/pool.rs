@@ -6,7 +6,7 @@ use crate::error::{Error, Result};
 
 pub async fn create_pool(config: DatabaseConfig) -> Result<PgPool> {
     PgPoolOptions::new()
-        .max_connections(5)
+        .max_connections(config.max_connections)
         .acquire_timeout(Duration::from_secs(3))
         .connect_with(config.connection_options())
         .await
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use sqlx::{postgres::PgRow, PgConnection, Row};
+use uuid::Uuid;
+
+use crate::{
+    error::{Error, Result},
+    models::database as DB,
+};
+
+pub const MAX_TASK_NAME_CHARS: usize = 255;
+pub const MAX_TASK_CATEGORY_CHARS: usize = 100;
+/// Longest task that can be created from a duration (7 days)
+pub const MAX_TASK_MINUTES: i64 = 10080;
+
+/// A task about to be written, with its times already resolved to UTC
+pub struct NewTask<'a> {
+    pub schedule_date: NaiveDate, // local day the task starts on
+    pub end_date: NaiveDate,      // local day end_time falls on
+    pub name: &'a str,
+    pub category: &'a str,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub ical_uid: Option<&'a str>, // set for tasks imported from a calendar
+}
+
+impl NewTask<'_> {
+    /// Applies the same limits as the tasks API, so every writer reports bad input as 400
+    pub fn validate(&self) -> Result<()> {
+        let name_chars = self.name.chars().count();
+        if name_chars == 0 || name_chars > MAX_TASK_NAME_CHARS {
+            return Err(Error::validation(format!(
+                "Task name must be between 1 and {MAX_TASK_NAME_CHARS} characters"
+            )));
+        }
+
+        let category_chars = self.category.chars().count();
+        if category_chars == 0 || category_chars > MAX_TASK_CATEGORY_CHARS {
+            return Err(Error::validation(format!(
+                "Category must be between 1 and {MAX_TASK_CATEGORY_CHARS} characters"
+            )));
+        }
+
+        if self.end_time <= self.start_time {
+            return Err(Error::validation("End time must be after start time"));
+        }
+        if self.end_date < self.schedule_date {
+            return Err(Error::validation("End date cannot be before date"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Serializes task writes for a user until the transaction ends, so concurrent writes
+/// can't both pass an overlap check
+pub async fn lock_user_tasks(conn: &mut PgConnection, user_id: Uuid) -> Result<()> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1::text))")
+        .bind(user_id)
+        .execute(conn)
+        .await
+        .map_err(Error::from)?;
+
+    Ok(())
+}
+
+/// Validates and inserts a task
+///
+/// Schedules and their counters are maintained by the tasks triggers
+pub async fn insert_task(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    task: &NewTask<'_>,
+) -> Result<DB::Task> {
+    task.validate()?;
+
+    sqlx::query_as::<_, DB::Task>(
+        "INSERT INTO tasks
+         (user_id, schedule_date, end_date, name, category, start_time, end_time, ical_uid)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+         RETURNING id, user_id, schedule_date, end_date, name, category,
+                   start_time, end_time, completed, created_at, updated_at",
+    )
+    .bind(user_id)
+    .bind(task.schedule_date)
+    .bind(task.end_date)
+    .bind(task.name)
+    .bind(task.category)
+    .bind(task.start_time)
+    .bind(task.end_time)
+    .bind(task.ical_uid)
+    .fetch_one(conn)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db_err) if db_err.is_foreign_key_violation() => {
+            Error::not_found(format!("User {}", user_id))
+        }
+        e => Error::Database(e),
+    })
+}
+
+/// Rejects a time span overlapping the user's tasks or recurring occurrences
+///
+/// Takes [`lock_user_tasks`] first. Conflicts are reported by task ID, or by recurring
+/// task ID for occurrences; `exclude` leaves out the task being moved
+pub async fn check_overlaps(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    exclude: Option<Uuid>,
+    tz: Tz,
+) -> Result<()> {
+    lock_user_tasks(conn, user_id).await?;
+
+    let mut conflicts: Vec<Uuid> = sqlx::query_scalar(
+        "SELECT id
+         FROM tasks
+         WHERE user_id = $1 AND start_time < $3 AND end_time > $2
+           AND ($4::uuid IS NULL OR id <> $4)
+         ORDER BY start_time",
+    )
+    .bind(user_id)
+    .bind(start)
+    .bind(end)
+    .bind(exclude)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(Error::from)?;
+
+    // Occurrences last at most a day, so one day either side covers them
+    let local_start = start.with_timezone(&tz).date_naive();
+    let local_end = end.with_timezone(&tz).date_naive();
+    let occurrences = recurring_occurrences(
+        conn,
+        user_id,
+        local_start.pred_opt().unwrap_or(local_start),
+        local_end,
+        tz,
+    )
+    .await?;
+    for occurrence in occurrences {
+        if occurrence.start_time < end
+            && occurrence.end_time > start
+            && !conflicts.contains(&occurrence.id)
+        {
+            conflicts.push(occurrence.id);
+        }
+    }
+
+    if conflicts.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Overlap(conflicts))
+    }
+}
+
+/// Expands a user's recurring tasks into occurrences starting between `from` and `to`
+///
+/// Occurrences are returned as tasks (ID of the recurring task, times in UTC),
+/// with per-occurrence edits applied and cancelled ones left out
+pub async fn recurring_occurrences(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    from: NaiveDate,
+    to: NaiveDate,
+    tz: Tz,
+) -> Result<Vec<DB::Task>> {
+    let series = sqlx::query(
+        "SELECT id, user_id, name, category, start_time, duration_minutes,
+                frequency, interval, weekdays, start_date, until, count,
+                created_at, updated_at
+         FROM recurring_tasks
+         WHERE user_id = $1 AND start_date <= $3 AND (until IS NULL OR until >= $2)",
+    )
+    .bind(user_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(Error::from)?
+    .into_iter()
+    .map(map_recurring_row)
+    .collect::<Result<Vec<_>>>()?;
+
+    if series.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let ids: Vec<Uuid> = series.iter().map(|s| s.id).collect();
+    let exceptions = sqlx::query(
+        "SELECT recurring_task_id, occurrence_date, name, category, start_time,
+                duration_minutes, completed, cancelled, created_at, updated_at
+         FROM recurring_task_exceptions
+         WHERE recurring_task_id = ANY($1) AND occurrence_date BETWEEN $2 AND $3",
+    )
+    .bind(&ids)
+    .bind(from)
+    .bind(to)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(Error::from)?
+    .into_iter()
+    .map(|row| {
+        let exception = map_exception_row(row)?;
+        Ok((
+            (exception.recurring_task_id, exception.occurrence_date),
+            exception,
+        ))
+    })
+    .collect::<Result<HashMap<_, _>>>()?;
+
+    let mut occurrences = Vec::new();
+    for recurring in &series {
+        for date in recurring.occurrences(from, to) {
+            let exception = exceptions.get(&(recurring.id, date));
+            if exception.is_some_and(|e| e.cancelled) {
+                continue;
+            }
+            occurrences.push(occurrence_task(recurring, date, exception, tz));
+        }
+    }
+
+    occurrences.sort_by_key(|task| task.start_time);
+    Ok(occurrences)
+}
+
+/// Builds the task for one occurrence of a recurring task
+pub fn occurrence_task(
+    recurring: &DB::RecurringTask,
+    date: NaiveDate,
+    exception: Option<&DB::RecurringTaskException>,
+    tz: Tz,
+) -> DB::Task {
+    let start_time = exception
+        .and_then(|e| e.start_time)
+        .unwrap_or(recurring.start_time);
+    let duration = exception
+        .and_then(|e| e.duration_minutes)
+        .unwrap_or(recurring.duration_minutes);
+
+    let start = date.and_time(start_time);
+    let end = start
+        .checked_add_signed(TimeDelta::minutes(duration as i64))
+        .unwrap_or(NaiveDateTime::MAX);
+
+    DB::Task {
+        id: recurring.id,
+        user_id: recurring.user_id,
+        schedule_date: date,
+        end_date: end.date(),
+        name: exception
+            .and_then(|e| e.name.clone())
+            .unwrap_or_else(|| recurring.name.clone()),
+        category: exception
+            .and_then(|e| e.category.clone())
+            .unwrap_or_else(|| recurring.category.clone()),
+        start_time: generated_utc(start, tz),
+        end_time: generated_utc(end, tz),
+        completed: exception.is_some_and(|e| e.completed),
+        created_at: recurring.created_at,
+        updated_at: exception.map_or(recurring.updated_at, |e| e.updated_at),
+    }
+}
+
+/// The UTC span of a local day, from midnight to the next midnight
+pub fn day_bounds(date: NaiveDate, tz: Tz) -> (DateTime<Utc>, DateTime<Utc>) {
+    let next = date.succ_opt().unwrap_or(date);
+    (
+        generated_utc(date.and_time(Default::default()), tz),
+        generated_utc(next.and_time(Default::default()), tz),
+    )
+}
+
+/// Places a generated local time in UTC
+///
+/// Unlike user input, generated times can't be rejected: times skipped by a DST
+/// spring-forward move forward by an hour instead
+fn generated_utc(local: NaiveDateTime, tz: Tz) -> DateTime<Utc> {
+    tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| {
+            let later = local.checked_add_signed(TimeDelta::hours(1))?;
+            tz.from_local_datetime(&later).earliest()
+        })
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(|| local.and_utc())
+}
+
+pub fn map_recurring_row(row: PgRow) -> Result<DB::RecurringTask> {
+    Ok(DB::RecurringTask {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        name: row.get("name"),
+        category: row.get("category"),
+        start_time: row.get("start_time"),
+        duration_minutes: row.get("duration_minutes"),
+        frequency: row.get("frequency"),
+        interval: row.get("interval"),
+        weekdays: row.get("weekdays"),
+        start_date: row.get("start_date"),
+        until: row.get("until"),
+        count: row.get("count"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+pub fn map_exception_row(row: PgRow) -> Result<DB::RecurringTaskException> {
+    Ok(DB::RecurringTaskException {
+        recurring_task_id: row.get("recurring_task_id"),
+        occurrence_date: row.get("occurrence_date"),
+        name: row.get("name"),
+        category: row.get("category"),
+        start_time: row.get("start_time"),
+        duration_minutes: row.get("duration_minutes"),
+        completed: row.get("completed"),
+        cancelled: row.get("cancelled"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
@@ -6,6 +6,7 @@ use axum::{
 use serde_json::json;
 use thiserror::Error;
 use tracing::{error, warn};
+use uuid::Uuid;
 
 pub type Result<T> = core::result::Result<T, Error>;
 
@@ -14,12 +15,24 @@ pub enum Error {
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
 
+    #[error("Migration error: {0}")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+
     #[error("Validation error: {0}")]
     Validation(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     #[error("{0} not found")]
     NotFound(String),
 
+    #[error("{0} already exists")]
+    Conflict(String),
+
+    #[error("Overlaps with {} existing task(s)", .0.len())]
+    Overlap(Vec<Uuid>),
+
     #[error("Internal Server error: {0}")]
     InternalServerError(String),
 }
@@ -29,40 +42,73 @@ impl Error {
         Self::Validation(msg.into())
     }
 
+    pub fn forbidden(msg: impl Into<String>) -> Self {
+        Self::Forbidden(msg.into())
+    }
+
     pub fn not_found(msg: impl Into<String>) -> Self {
         Self::NotFound(msg.into())
     }
+
+    pub fn conflict(msg: impl Into<String>) -> Self {
+        Self::Conflict(msg.into())
+    }
+
+    /// HTTP status this error is reported with
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::Database(_) | Error::Migration(_) | Error::InternalServerError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            Error::Validation(_) => StatusCode::BAD_REQUEST,
+            Error::Forbidden(_) => StatusCode::FORBIDDEN,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Conflict(_) | Error::Overlap(_) => StatusCode::CONFLICT,
+        }
+    }
+
+    /// Message safe to return to clients (internal details stay in the logs)
+    pub fn public_message(&self) -> String {
+        match self {
+            Error::Database(_) | Error::Migration(_) => "Internal server error".to_string(),
+            Error::Validation(msg)
+            | Error::Forbidden(msg)
+            | Error::NotFound(msg)
+            | Error::Conflict(msg)
+            | Error::InternalServerError(msg) => msg.clone(),
+            Error::Overlap(_) => self.to_string(),
+        }
+    }
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
-        let (status, error_message) = match &self {
-            Error::Database(e) => {
-                error!(error = %e, "Database error occurred");
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Internal server error".to_string(),
-                )
-            }
-            Error::Validation(msg) => {
-                warn!(message = %msg, "Validation error");
-                (StatusCode::BAD_REQUEST, msg.clone())
-            }
-            Error::NotFound(msg) => {
-                warn!(message = %msg, "Resource not found");
-                (StatusCode::NOT_FOUND, msg.clone())
-            }
+        match &self {
+            Error::Database(e) => error!(error = %e, "Database error occurred"),
+            Error::Migration(e) => error!(error = %e, "Migration error occurred"),
+            Error::Validation(msg) => warn!(message = %msg, "Validation error"),
+            Error::Forbidden(msg) => warn!(message = %msg, "Access denied"),
+            Error::NotFound(msg) => warn!(message = %msg, "Resource not found"),
+            Error::Conflict(msg) => warn!(message = %msg, "Resource conflict"),
+            Error::Overlap(ids) => warn!(conflicts = ids.len(), "Task overlap"),
             Error::InternalServerError(msg) => {
-                error!(error = %msg, "Internal server error occurred");
-                (StatusCode::INTERNAL_SERVER_ERROR, msg.clone())
+                error!(error = %msg, "Internal server error occurred")
             }
-        };
+        }
 
-        let body = Json(json!({
+        let status = self.status();
+        let error_message = self.public_message();
+
+        let mut body = json!({
             "error": error_message,
             "status": status.as_u16()
-        }));
+        });
+
+        // Lets clients show (or resolve) what the task collided with
+        if let Error::Overlap(ids) = &self {
+            body["conflicting_task_ids"] = json!(ids);
+        }
 
-        (status, body).into_response()
+        (status, Json(body)).into_response()
     }
 }
@@ -1,4 +1,6 @@
 pub mod database;
 pub mod error;
 pub mod models;
+pub mod ndjson;
+pub mod routes;
 pub mod services;
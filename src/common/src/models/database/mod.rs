@@ -1,9 +1,15 @@
 mod profiles;
+mod rankings;
+mod recurring_tasks;
+mod routines;
 mod schedules;
 mod tasks;
 mod users;
 
-pub use profiles::{PersonalityScores, Profile};
+pub use profiles::{parse_timezone, PersonalityScores, Profile};
+pub use rankings::{RankedItem, Ranking};
+pub use recurring_tasks::{RecurringTask, RecurringTaskException, FREQ_DAILY, FREQ_WEEKLY};
+pub use routines::Routine;
 pub use schedules::Schedule;
 pub use tasks::Task;
 pub use users::User;
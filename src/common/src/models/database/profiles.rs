@@ -1,14 +1,18 @@
 use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use sqlx::types::JsonValue;
 use sqlx::types::Uuid;
 
+use crate::error::{Error, Result};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Profile {
     pub user_id: Uuid,
     pub cluster: i32,
     pub preferences: Vec<String>,
     pub personality_scores: JsonValue, // this is a single JSONB in Postgres
+    pub timezone: String,              // IANA name, e.g. Europe/Berlin
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -32,6 +36,17 @@ impl Profile {
     pub fn get_typed_scores(&self) -> Option<PersonalityScores> {
         serde_json::from_value(self.personality_scores.clone()).ok()
     }
+
+    // Helper function to parse the stored timezone, falling back to UTC
+    pub fn get_timezone(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+}
+
+/// Parses an IANA timezone name (e.g. "Europe/Berlin"), rejecting unknown zones
+pub fn parse_timezone(name: &str) -> Result<Tz> {
+    name.parse()
+        .map_err(|_| Error::validation(format!("Unknown timezone: {}", name)))
 }
 
 impl Default for PersonalityScores {
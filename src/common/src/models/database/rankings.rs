@@ -0,0 +1,31 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::JsonValue;
+use sqlx::types::Uuid;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Ranking {
+    pub user_id: Uuid,
+    pub ranked_preferences: JsonValue, // JSONB array of RankedItem
+    pub ranked_categories: JsonValue,  // JSONB array of RankedItem
+    pub ranked_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedItem {
+    pub name: String,
+    pub score: f32,
+}
+
+impl Ranking {
+    // Helper functions to give us types and access to the ranked JSONB columns
+    pub fn get_typed_preferences(&self) -> Vec<RankedItem> {
+        serde_json::from_value(self.ranked_preferences.clone()).unwrap_or_default()
+    }
+
+    pub fn get_typed_categories(&self) -> Vec<RankedItem> {
+        serde_json::from_value(self.ranked_categories.clone()).unwrap_or_default()
+    }
+}
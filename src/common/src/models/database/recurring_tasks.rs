@@ -0,0 +1,257 @@
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+
+pub const FREQ_DAILY: &str = "daily";
+pub const FREQ_WEEKLY: &str = "weekly";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurringTask {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub category: String,
+    pub start_time: NaiveTime, // local to the user's timezone
+    pub duration_minutes: i32,
+    pub frequency: String,  // daily | weekly
+    pub interval: i32,      // every N days/weeks
+    pub weekdays: Vec<i16>, // ISO weekdays (1 = Monday), weekly only
+    pub start_date: NaiveDate,
+    pub until: Option<NaiveDate>, // inclusive
+    pub count: Option<i32>,       // total occurrences from start_date
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurringTaskException {
+    pub recurring_task_id: Uuid,
+    pub occurrence_date: NaiveDate,
+    pub name: Option<String>,
+    pub category: Option<String>,
+    pub start_time: Option<NaiveTime>,
+    pub duration_minutes: Option<i32>,
+    pub completed: bool,
+    pub cancelled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl RecurringTask {
+    /// Dates the task occurs on between `from` and `to` (inclusive), in order
+    ///
+    /// Occurrences before `from` are counted rather than walked, so `count` is honoured
+    /// without expanding the series from start_date
+    pub fn occurrences(&self, from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+        let last = match self.until {
+            Some(until) => until.min(to),
+            None => to,
+        };
+        let first = from.max(self.start_date);
+        if first > last {
+            return Vec::new();
+        }
+
+        let limit = self.count.map(|c| c.max(0) as usize);
+        let interval = self.interval.max(1) as i64;
+        let weekly = self.frequency == FREQ_WEEKLY;
+        let mut seen = self.occurrences_before(first, interval);
+
+        // Daily tasks step straight from one occurrence to the next
+        let (mut current, step) = if weekly {
+            (first, 1)
+        } else {
+            let offset = (seen as i64 * interval) as u64;
+            match self.start_date.checked_add_days(Days::new(offset)) {
+                Some(current) => (current, interval as u64),
+                None => return Vec::new(),
+            }
+        };
+
+        let mut dates = Vec::new();
+        while current <= last && limit.is_none_or(|l| seen < l) {
+            let matches = !weekly
+                || (week_index(self.start_date, current) % interval == 0
+                    && self.on_weekday(current));
+
+            if matches {
+                seen += 1;
+                dates.push(current);
+            }
+
+            current = match current.checked_add_days(Days::new(step)) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        dates
+    }
+
+    /// Whether `date` is one of this task's occurrences
+    pub fn occurs_on(&self, date: NaiveDate) -> bool {
+        self.occurrences(date, date).contains(&date)
+    }
+
+    // Number of occurrences from start_date up to (excluding) `date`
+    fn occurrences_before(&self, date: NaiveDate, interval: i64) -> usize {
+        if date <= self.start_date {
+            return 0;
+        }
+
+        if self.frequency != FREQ_WEEKLY {
+            let days = (date - self.start_date).num_days();
+            return ((days + interval - 1) / interval) as usize;
+        }
+
+        let start_day = self.start_date.weekday().number_from_monday() as i16;
+        let date_day = date.weekday().number_from_monday() as i16;
+        let weeks = week_index(self.start_date, date);
+        if weeks == 0 {
+            return self.weekdays_between(start_day, date_day);
+        }
+
+        // Every active week before date's, minus the days before start_date in the first
+        let active_weeks = ((weeks + interval - 1) / interval) as usize;
+        let mut seen =
+            active_weeks * self.weekdays_between(1, 8) - self.weekdays_between(1, start_day);
+        if weeks % interval == 0 {
+            seen += self.weekdays_between(1, date_day);
+        }
+        seen
+    }
+
+    // Number of the task's ISO weekdays in `from..to`
+    fn weekdays_between(&self, from: i16, to: i16) -> usize {
+        (from..to).filter(|&day| self.on_iso_weekday(day)).count()
+    }
+
+    fn on_weekday(&self, date: NaiveDate) -> bool {
+        self.on_iso_weekday(date.weekday().number_from_monday() as i16)
+    }
+
+    // Weekly tasks without explicit weekdays repeat on start_date's weekday
+    fn on_iso_weekday(&self, weekday: i16) -> bool {
+        if self.weekdays.is_empty() {
+            weekday == self.start_date.weekday().number_from_monday() as i16
+        } else {
+            self.weekdays.contains(&weekday)
+        }
+    }
+}
+
+// Weeks between the weeks `start` and `date` fall in
+fn week_index(start: NaiveDate, date: NaiveDate) -> i64 {
+    (week_start(date) - week_start(start)).num_weeks()
+}
+
+// Monday of the week `date` falls in
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Days::new(date.weekday().num_days_from_monday() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn recurring(frequency: &str, interval: i32, weekdays: Vec<i16>) -> RecurringTask {
+        RecurringTask {
+            id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            name: "Gym".to_string(),
+            category: "Exercise".to_string(),
+            start_time: NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+            duration_minutes: 60,
+            frequency: frequency.to_string(),
+            interval,
+            weekdays,
+            // A Wednesday
+            start_date: date(2024, 1, 3),
+            until: None,
+            count: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    // Expands day by day from start_date, as a reference for the direct computation
+    fn walked(task: &RecurringTask, from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+        let interval = task.interval.max(1) as i64;
+        let mut dates = Vec::new();
+        let mut seen = 0;
+        let mut current = task.start_date;
+        while current <= to && task.until.is_none_or(|u| current <= u) {
+            let matches = if task.frequency == FREQ_WEEKLY {
+                week_index(task.start_date, current) % interval == 0 && task.on_weekday(current)
+            } else {
+                (current - task.start_date).num_days() % interval == 0
+            };
+            if matches && task.count.is_none_or(|c| seen < c as usize) {
+                seen += 1;
+                if current >= from {
+                    dates.push(current);
+                }
+            }
+            current = current.succ_opt().unwrap();
+        }
+        dates
+    }
+
+    #[test]
+    fn occurrences_match_a_day_by_day_expansion() {
+        let tasks = [
+            recurring(FREQ_DAILY, 1, vec![]),
+            recurring(FREQ_DAILY, 3, vec![]),
+            recurring(FREQ_WEEKLY, 1, vec![]),
+            recurring(FREQ_WEEKLY, 2, vec![1, 3, 5]),
+            recurring(FREQ_WEEKLY, 3, vec![2, 7]),
+        ];
+
+        for mut task in tasks {
+            for count in [None, Some(1), Some(5), Some(40)] {
+                task.count = count;
+                for offset in 0..60 {
+                    let from = date(2023, 12, 20) + Days::new(offset);
+                    let to = from + Days::new(30);
+                    assert_eq!(
+                        task.occurrences(from, to),
+                        walked(&task, from, to),
+                        "{} every {} {:?}, count {count:?}, from {from}",
+                        task.frequency,
+                        task.interval,
+                        task.weekdays,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn occurrences_far_from_start_honour_count() {
+        let mut task = recurring(FREQ_WEEKLY, 2, vec![1, 3]);
+        let far = date(2124, 1, 1);
+        let to = far + Days::new(30);
+        let dates = task.occurrences(far, to);
+        assert!(!dates.is_empty());
+        assert_eq!(dates, walked(&task, far, to));
+
+        task.count = Some(10);
+        assert!(task.occurrences(far, far + Days::new(30)).is_empty());
+    }
+
+    #[test]
+    fn occurrences_stop_at_until() {
+        let mut task = recurring(FREQ_DAILY, 1, vec![]);
+        task.until = Some(date(2024, 1, 5));
+
+        assert_eq!(
+            task.occurrences(date(2024, 1, 1), date(2024, 1, 31)),
+            [date(2024, 1, 3), date(2024, 1, 4), date(2024, 1, 5)]
+        );
+        assert!(!task.occurs_on(date(2024, 1, 6)));
+    }
+}
@@ -0,0 +1,13 @@
+use chrono::{DateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Routine {
+    pub user_id: Uuid,
+    pub work_start_time: NaiveTime,
+    pub work_end_time: NaiveTime,
+    pub sleep_time: NaiveTime,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
@@ -3,11 +3,12 @@ use serde::{Deserialize, Serialize};
 use sqlx::{prelude::FromRow, types::Uuid};
 
 // This is for internal use to represent how it's stored in DB
-#[derive(Debug, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Task {
     pub id: Uuid,
     pub user_id: Uuid,
-    pub schedule_date: NaiveDate,
+    pub schedule_date: NaiveDate, // start day, counted in its schedule
+    pub end_date: NaiveDate,      // day end_time falls on (later for overnight tasks)
     pub name: String,
     pub category: String,
     pub start_time: DateTime<Utc>,
@@ -0,0 +1,187 @@
+use std::pin::pin;
+
+use axum::{
+    body::Body,
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use futures_util::{Stream, TryStreamExt};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+
+pub const CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Response header carrying the cursor of the next export page, absent on the last page
+pub const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+/// Rows per export response
+///
+/// API Gateway buffers Lambda responses and rejects bodies over 6 MB, so larger exports
+/// are split into pages well under that
+pub const MAX_EXPORT_ROWS: i64 = 5_000;
+
+/// How far before `since` incremental exports start reading
+///
+/// `updated_at` is stamped when a write's transaction starts but only becomes visible
+/// when it commits, so a row can show up after later-stamped rows were exported. No
+/// write outlives the longest Lambda timeout (15 minutes), so re-reading that window
+/// catches it; rows inside the window are exported again and consumers upsert by ID
+pub const SINCE_OVERLAP: TimeDelta = TimeDelta::minutes(15);
+
+/// The `updated_at` an export with `since` starts reading from
+pub fn overlapped_since(since: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+    since.map(|since| {
+        since
+            .checked_sub_signed(SINCE_OVERLAP)
+            .unwrap_or(DateTime::<Utc>::MIN_UTC)
+    })
+}
+
+/// Position in the (updated_at, id) ordering of an export
+pub struct ExportCursor {
+    pub updated_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl ExportCursor {
+    /// Opaque to clients: `<updated_at micros>_<id>`
+    pub fn encode(&self) -> String {
+        format!(
+            "{}_{}",
+            self.updated_at.timestamp_micros(),
+            self.id.simple()
+        )
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self> {
+        let invalid = || Error::validation("Invalid cursor");
+        let (updated_at, id) = cursor.split_once('_').ok_or_else(invalid)?;
+
+        Ok(Self {
+            updated_at: updated_at
+                .parse()
+                .ok()
+                .and_then(DateTime::from_timestamp_micros)
+                .ok_or_else(invalid)?,
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Writes up to `limit` of `rows` as newline-delimited JSON
+///
+/// `rows` should yield one row more than `limit` when there is another page; that row
+/// isn't written, and the cursor of the last written row is sent in
+/// [`NEXT_CURSOR_HEADER`]. Rows are serialized as they're read, so only the body is held
+pub async fn page<T, S>(
+    rows: S,
+    limit: i64,
+    cursor: impl Fn(&T) -> ExportCursor,
+) -> Result<Response>
+where
+    T: Serialize,
+    S: Stream<Item = Result<T>>,
+{
+    let mut rows = pin!(rows);
+    let mut body = Vec::new();
+    let mut written = 0;
+    let mut last = None;
+    let mut next_cursor = None;
+
+    while let Some(row) = rows.try_next().await? {
+        if written == limit {
+            next_cursor = last.take();
+            break;
+        }
+        serde_json::to_writer(&mut body, &row)
+            .map_err(|e| Error::InternalServerError(e.to_string()))?;
+        body.push(b'\n');
+        written += 1;
+        last = Some(cursor(&row));
+    }
+
+    let mut response = ([(header::CONTENT_TYPE, CONTENT_TYPE)], Body::from(body)).into_response();
+    if let Some(next_cursor) = next_cursor {
+        let value = HeaderValue::from_str(&next_cursor.encode())
+            .map_err(|e| Error::InternalServerError(e.to_string()))?;
+        response.headers_mut().insert(NEXT_CURSOR_HEADER, value);
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::to_bytes;
+    use futures_util::stream;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Row {
+        id: Uuid,
+        updated_at: DateTime<Utc>,
+    }
+
+    fn rows(count: usize) -> Vec<Row> {
+        (0..count)
+            .map(|i| Row {
+                id: Uuid::new_v4(),
+                updated_at: DateTime::from_timestamp(i as i64, 0).unwrap(),
+            })
+            .collect()
+    }
+
+    fn row_cursor(row: &Row) -> ExportCursor {
+        ExportCursor {
+            updated_at: row.updated_at,
+            id: row.id,
+        }
+    }
+
+    async fn body_lines(response: Response) -> usize {
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.ends_with(b"\n"));
+        body.split(|b| *b == b'\n').count() - 1
+    }
+
+    #[tokio::test]
+    async fn page_sends_cursor_of_last_written_row() {
+        let rows = rows(3);
+        let expected = row_cursor(&rows[1]).encode();
+
+        let response = page(stream::iter(rows.into_iter().map(Ok)), 2, row_cursor)
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers()[NEXT_CURSOR_HEADER], expected.as_str());
+        assert_eq!(body_lines(response).await, 2);
+    }
+
+    #[tokio::test]
+    async fn page_omits_cursor_on_last_page() {
+        let response = page(stream::iter(rows(2).into_iter().map(Ok)), 2, row_cursor)
+            .await
+            .unwrap();
+
+        assert!(response.headers().get(NEXT_CURSOR_HEADER).is_none());
+        assert_eq!(body_lines(response).await, 2);
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = ExportCursor {
+            updated_at: DateTime::from_timestamp_micros(1_732_500_000_123_456).unwrap(),
+            id: Uuid::new_v4(),
+        };
+
+        let decoded = ExportCursor::decode(&cursor.encode()).unwrap();
+
+        assert_eq!(decoded.updated_at, cursor.updated_at);
+        assert_eq!(decoded.id, cursor.id);
+        assert!(ExportCursor::decode("not-a-cursor").is_err());
+    }
+}
@@ -0,0 +1,452 @@
+use std::{env, fmt, fs};
+
+use axum::{
+    handler::Handler,
+    http::Method,
+    middleware,
+    routing::{on, MethodFilter},
+    Router,
+};
+
+use crate::{
+    error::{Error, Result},
+    services::mw_access::require_scope,
+};
+
+/// A single method + path served by a lambda, e.g. `GET /v1/user/:user_id/schedule`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RouteSpec {
+    pub method: String,
+    pub path: String,
+}
+
+impl fmt::Display for RouteSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.method, self.path)
+    }
+}
+
+/// Router builder that records every route it registers, so the router is the single
+/// source of truth for what a lambda serves
+pub struct ManifestRouter<S> {
+    router: Router<S>,
+    public: Router<S>,
+    manifest: Vec<RouteSpec>,
+}
+
+impl<S> Default for ManifestRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> ManifestRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            router: Router::new(),
+            public: Router::new(),
+            manifest: Vec::new(),
+        }
+    }
+
+    /// Registers `handler` for `method` on `path`
+    pub fn route<H, T>(mut self, method: Method, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        self.router = self.router.route(path, on(method_filter(&method), handler));
+        self.record(method, path);
+        self
+    }
+
+    /// Registers `handler` for `method` on `path`, only for callers holding `scope`
+    pub fn scoped_route<H, T>(
+        mut self,
+        method: Method,
+        path: &str,
+        handler: H,
+        scope: &'static str,
+    ) -> Self
+    where
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        let method_router = on(method_filter(&method), handler)
+            .route_layer(middleware::from_fn_with_state(scope, require_scope));
+        self.router = self.router.route(path, method_router);
+        self.record(method, path);
+        self
+    }
+
+    /// Registers `handler` for `method` on `path` without JWT authentication
+    ///
+    /// The handler must authenticate the request itself (e.g. with a feed token), since
+    /// clients like calendar apps can't send an `Authorization` header
+    pub fn public_route<H, T>(mut self, method: Method, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        self.public = self.public.route(path, on(method_filter(&method), handler));
+        self.record(method, path);
+        self
+    }
+
+    /// Every registered route, sorted by path then method
+    pub fn manifest(&self) -> Vec<RouteSpec> {
+        let mut manifest = self.manifest.clone();
+        manifest.sort_by(|a, b| a.path.cmp(&b.path).then(a.method.cmp(&b.method)));
+        manifest
+    }
+
+    /// The authenticated routes; panics if public routes were registered, as those
+    /// would be silently dropped (use [`Self::into_parts`])
+    pub fn into_router(self) -> Router<S> {
+        assert!(
+            !self.public.has_routes(),
+            "public routes registered; use into_parts"
+        );
+        self.router
+    }
+
+    /// Splits into (authenticated, public) routers, so auth layers can be applied to the
+    /// first only
+    pub fn into_parts(self) -> (Router<S>, Router<S>) {
+        (self.router, self.public)
+    }
+
+    fn record(&mut self, method: Method, path: &str) {
+        self.manifest.push(RouteSpec {
+            method: method.to_string(),
+            path: path.to_string(),
+        });
+    }
+}
+
+fn method_filter(method: &Method) -> MethodFilter {
+    MethodFilter::try_from(method.clone())
+        .unwrap_or_else(|_| panic!("Unsupported route method {method}"))
+}
+
+/// Handles the route manifest commands a lambda binary accepts instead of serving:
+///
+/// - `--routes`: prints the route manifest
+/// - `--check-routes <template.yaml>`: fails if `function`'s API events in the SAM template
+///   differ from the manifest, or a served path has no OPTIONS event for CORS preflights
+///
+/// Returns `Ok(true)` if a command was handled and the binary should exit.
+pub fn handle_cli(manifest: &[RouteSpec], function: &str) -> Result<bool> {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("--routes") => {
+            for route in manifest {
+                println!("{route}");
+            }
+            Ok(true)
+        }
+        Some("--check-routes") => {
+            let path = args.get(1).map(String::as_str).unwrap_or("template.yaml");
+            let template = fs::read_to_string(path)
+                .map_err(|e| Error::validation(format!("Failed to read {path}: {e}")))?;
+
+            check_against_template(manifest, &template, function)?;
+            println!("{function}: {} routes match {path}", manifest.len());
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Compares the manifest with the `Type: Api` events of `function` in a SAM template
+///
+/// The API doesn't answer preflights itself, so every served path must also route
+/// OPTIONS to the function, where the CORS middleware handles it
+pub fn check_against_template(
+    manifest: &[RouteSpec],
+    template: &str,
+    function: &str,
+) -> Result<()> {
+    let mut expected = template_routes(template, function)?;
+    expected.sort();
+
+    let mut paths: Vec<&str> = manifest.iter().map(|r| r.path.as_str()).collect();
+    paths.sort();
+    paths.dedup();
+    let mut actual = manifest.to_vec();
+    actual.extend(paths.into_iter().map(|path| RouteSpec {
+        method: Method::OPTIONS.to_string(),
+        path: path.to_string(),
+    }));
+    actual.sort();
+
+    let missing: Vec<String> = actual
+        .iter()
+        .filter(|r| !expected.contains(r))
+        .map(RouteSpec::to_string)
+        .collect();
+    let extra: Vec<String> = expected
+        .iter()
+        .filter(|r| !actual.contains(r))
+        .map(RouteSpec::to_string)
+        .collect();
+
+    if missing.is_empty() && extra.is_empty() {
+        return Ok(());
+    }
+
+    Err(Error::validation(format!(
+        "{function} routes disagree with template. Missing from template: [{}]. Not served by router: [{}]",
+        missing.join(", "),
+        extra.join(", ")
+    )))
+}
+
+/// Extracts the API events of `function` from a SAM template, converting `{param}` path
+/// segments to axum's `:param` form
+pub fn template_routes(template: &str, function: &str) -> Result<Vec<RouteSpec>> {
+    let header = format!("{function}:");
+    let mut lines = template
+        .lines()
+        .skip_while(|line| !(indent(line) == 2 && line.trim() == header));
+
+    if lines.next().is_none() {
+        return Err(Error::not_found(format!("{function} in template")));
+    }
+
+    let mut routes = Vec::new();
+    let (mut path, mut method) = (None, None);
+
+    for line in lines {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        // Next resource
+        if indent(line) <= 2 {
+            break;
+        }
+
+        if let Some(value) = trimmed.strip_prefix("Path:") {
+            path = Some(scalar(value).to_string());
+        } else if let Some(value) = trimmed.strip_prefix("Method:") {
+            method = Some(scalar(value).to_uppercase());
+        }
+
+        if let (Some(p), Some(m)) = (&path, &method) {
+            routes.push(RouteSpec {
+                method: m.clone(),
+                path: to_axum_path(p),
+            });
+            (path, method) = (None, None);
+        }
+    }
+
+    Ok(routes)
+}
+
+// A YAML scalar without its quotes or trailing comment
+fn scalar(value: &str) -> &str {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if let Some(rest) = value.strip_prefix(quote) {
+            return rest.split(quote).next().unwrap_or_default();
+        }
+    }
+    value.split(" #").next().unwrap_or_default().trim()
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+// `/v1/user/{user_id}` -> `/v1/user/:user_id`
+fn to_axum_path(path: &str) -> String {
+    path.split('/')
+        .map(
+            |segment| match segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                Some(param) => format!(":{param}"),
+                None => segment.to_string(),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, extract::Request, http::StatusCode, Extension};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::services::mw_access::{ADMIN_SCOPE, TASKS_READ_ALL_SCOPE};
+    use crate::services::mw_auth::AuthUser;
+
+    const TEMPLATE: &str = r#"
+Resources:
+  # Tasks API
+  TasksFunction:
+    Type: AWS::Serverless::Function
+    Properties:
+      Events:
+        GetTask:
+          Type: Api
+          Properties:
+            # Quoted values
+            Path: "/v1/user/{user_id}/tasks/{task_id}"
+            Method: 'get'
+
+        PutTask:
+          Type: Api
+          Properties:
+            Path: /v1/user/{user_id}/tasks/{task_id} # trailing comment
+            Method: put
+# A comment at column 0 doesn't end the resource
+        ListTasks:
+          Type: Api
+          Properties:
+            Method: GET
+            Path: /v1/tasks
+  OtherFunction:
+    Type: AWS::Serverless::Function
+    Properties:
+      Events:
+        Other:
+          Type: Api
+          Properties:
+            Path: /v1/other
+            Method: get
+"#;
+
+    fn spec(method: &str, path: &str) -> RouteSpec {
+        RouteSpec {
+            method: method.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn template_routes_reads_one_resource() {
+        let routes = template_routes(TEMPLATE, "TasksFunction").unwrap();
+
+        assert_eq!(
+            routes,
+            [
+                spec("GET", "/v1/user/:user_id/tasks/:task_id"),
+                spec("PUT", "/v1/user/:user_id/tasks/:task_id"),
+                spec("GET", "/v1/tasks"),
+            ]
+        );
+    }
+
+    #[test]
+    fn template_routes_reads_last_resource() {
+        let routes = template_routes(TEMPLATE, "OtherFunction").unwrap();
+
+        assert_eq!(routes, [spec("GET", "/v1/other")]);
+    }
+
+    #[test]
+    fn template_routes_rejects_unknown_function() {
+        let result = template_routes(TEMPLATE, "MissingFunction");
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn check_against_template_reports_differences() {
+        let manifest = [spec("GET", "/v1/tasks"), spec("POST", "/v1/tasks")];
+
+        let Err(Error::Validation(msg)) =
+            check_against_template(&manifest, TEMPLATE, "TasksFunction")
+        else {
+            panic!("expected a validation error");
+        };
+        assert!(msg.contains("Missing from template: [OPTIONS /v1/tasks, POST /v1/tasks]"));
+        assert!(msg.contains("GET /v1/user/:user_id/tasks/:task_id"));
+    }
+
+    #[test]
+    fn check_against_template_requires_preflights() {
+        let manifest = [spec("GET", "/v1/other")];
+
+        let Err(Error::Validation(msg)) =
+            check_against_template(&manifest, TEMPLATE, "OtherFunction")
+        else {
+            panic!("expected a validation error");
+        };
+        assert!(msg.contains("Missing from template: [OPTIONS /v1/other]"));
+
+        let template = format!(
+            "{TEMPLATE}        OtherPreflight:
+          Type: Api
+          Properties:
+            Path: /v1/other
+            Method: options
+"
+        );
+        check_against_template(&manifest, &template, "OtherFunction").unwrap();
+    }
+
+    #[test]
+    fn to_axum_path_converts_params() {
+        assert_eq!(
+            to_axum_path("/v1/user/{user_id}/tasks/{task_id}"),
+            "/v1/user/:user_id/tasks/:task_id"
+        );
+        assert_eq!(to_axum_path("/v1/tasks"), "/v1/tasks");
+        assert_eq!(to_axum_path("/v1/feed/{token}.ics"), "/v1/feed/{token}.ics");
+    }
+
+    async fn send_scoped(scopes: &[&str], uri: &str) -> StatusCode {
+        let user = AuthUser {
+            subject: "pipeline".to_string(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        };
+        let app = ManifestRouter::<()>::new()
+            .scoped_route(
+                Method::GET,
+                "/v1/tasks",
+                || async { "ok" },
+                TASKS_READ_ALL_SCOPE,
+            )
+            .route(Method::GET, "/v1/other", || async { "ok" })
+            .into_router()
+            .layer(Extension(user));
+
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        app.oneshot(req).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn scoped_route_forbids_caller_without_scope() {
+        assert_eq!(
+            send_scoped(&["tasks:read"], "/v1/tasks").await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn scoped_route_allows_scope_or_admin() {
+        assert_eq!(
+            send_scoped(&[TASKS_READ_ALL_SCOPE], "/v1/tasks").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send_scoped(&[ADMIN_SCOPE], "/v1/tasks").await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn scope_only_applies_to_its_route() {
+        assert_eq!(send_scoped(&[], "/v1/other").await, StatusCode::OK);
+    }
+}
@@ -1,52 +1,336 @@
+use std::{env, sync::Arc};
+
 use axum::{
     body::Body,
-    http::{header, HeaderValue, Method, Request, Response, StatusCode},
+    extract::State,
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode},
     middleware::Next,
 };
 
-pub async fn cors_middleware(req: Request<Body>, next: Next) -> Response<Body> {
-    let origin = req
+use crate::error::{Error, Result};
+
+const DEFAULT_METHODS: &str = "GET, POST, PUT, DELETE, PATCH, OPTIONS";
+const DEFAULT_HEADERS: &str = "authorization, content-type";
+const DEFAULT_MAX_AGE: u64 = 86400;
+
+/// Allowlist of origins, methods and headers for cross-origin requests
+pub struct CorsConfig {
+    /// Exact origins, or `*` for any origin (only without credentials)
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<Method>,
+    pub allowed_headers: Vec<HeaderName>,
+    pub allow_credentials: bool,
+    pub max_age: u64,
+}
+
+impl CorsConfig {
+    /// Loads the CORS config from the environment
+    ///
+    /// - `CORS_ALLOWED_ORIGINS`: comma-separated origins, e.g. `https://app.bustleit.com`
+    /// - `CORS_ALLOWED_METHODS`: defaults to `GET, POST, PUT, DELETE, PATCH, OPTIONS`
+    /// - `CORS_ALLOWED_HEADERS`: defaults to `authorization, content-type`
+    /// - `CORS_ALLOW_CREDENTIALS`: `true` to allow credentialed requests
+    /// - `CORS_MAX_AGE`: preflight cache time in seconds, defaults to 86400
+    pub fn from_env() -> Result<Self> {
+        let allowed_origins = split_list(&env::var("CORS_ALLOWED_ORIGINS").unwrap_or_default());
+
+        let allowed_methods = split_list(
+            &env::var("CORS_ALLOWED_METHODS").unwrap_or_else(|_| DEFAULT_METHODS.to_string()),
+        )
+        .iter()
+        .map(|m| {
+            Method::from_bytes(m.to_uppercase().as_bytes())
+                .map_err(|_| Error::validation(format!("Invalid CORS method: {m}")))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+        let allowed_headers = split_list(
+            &env::var("CORS_ALLOWED_HEADERS").unwrap_or_else(|_| DEFAULT_HEADERS.to_string()),
+        )
+        .iter()
+        .map(|h| {
+            HeaderName::from_bytes(h.as_bytes())
+                .map_err(|_| Error::validation(format!("Invalid CORS header: {h}")))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+        let allow_credentials = env::var("CORS_ALLOW_CREDENTIALS").is_ok_and(|v| v == "true");
+
+        let max_age = match env::var("CORS_MAX_AGE") {
+            Ok(v) => v
+                .parse()
+                .map_err(|_| Error::validation("CORS_MAX_AGE must be a number of seconds"))?,
+            Err(_) => DEFAULT_MAX_AGE,
+        };
+
+        if allow_credentials && allowed_origins.iter().any(|o| o == "*") {
+            return Err(Error::validation(
+                "CORS_ALLOWED_ORIGINS cannot contain '*' when credentials are allowed",
+            ));
+        }
+
+        Ok(Self {
+            allowed_origins,
+            allowed_methods,
+            allowed_headers,
+            allow_credentials,
+            max_age,
+        })
+    }
+
+    /// Returns the value for `Access-Control-Allow-Origin` if the origin is allowed
+    fn allow_origin(&self, origin: &str) -> Option<HeaderValue> {
+        if self.allowed_origins.iter().any(|o| o == origin) {
+            HeaderValue::from_str(origin).ok()
+        } else if self.allowed_origins.iter().any(|o| o == "*") {
+            Some(HeaderValue::from_static("*"))
+        } else {
+            None
+        }
+    }
+
+    fn allows_method(&self, method: &str) -> bool {
+        self.allowed_methods.iter().any(|m| m.as_str() == method)
+    }
+
+    fn allows_headers(&self, requested: &str) -> bool {
+        split_list(requested).iter().all(|h| {
+            self.allowed_headers
+                .iter()
+                .any(|allowed| allowed.as_str().eq_ignore_ascii_case(h))
+        })
+    }
+
+    fn join_methods(&self) -> String {
+        let methods: Vec<&str> = self.allowed_methods.iter().map(Method::as_str).collect();
+        methods.join(", ")
+    }
+
+    fn join_headers(&self) -> String {
+        let headers: Vec<&str> = self
+            .allowed_headers
+            .iter()
+            .map(HeaderName::as_str)
+            .collect();
+        headers.join(", ")
+    }
+
+    fn insert_common_headers(&self, headers: &mut HeaderMap, allow_origin: HeaderValue) {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        if self.allow_credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+}
+
+pub async fn cors_middleware(
+    State(config): State<Arc<CorsConfig>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response<Body> {
+    // Requests without an Origin header are not cross-origin
+    let Some(origin) = req
         .headers()
         .get(header::ORIGIN)
         .and_then(|h| h.to_str().ok())
-        .unwrap_or("*");
+        .map(str::to_string)
+    else {
+        return next.run(req).await;
+    };
 
-    let origin_value =
-        HeaderValue::from_str(origin).unwrap_or_else(|_| HeaderValue::from_static("*"));
+    let allow_origin = config.allow_origin(&origin);
+
+    let requested_method = req
+        .headers()
+        .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|h| h.to_str().ok());
 
     // Handle preflight requests
-    if req.method() == Method::OPTIONS {
-        Response::builder()
-            .status(StatusCode::OK)
-            .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin_value)
+    if let (&Method::OPTIONS, Some(requested_method)) = (req.method(), requested_method) {
+        let requested_headers = req
+            .headers()
+            .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or_default();
+
+        let allow_origin = match allow_origin {
+            Some(allow_origin)
+                if config.allows_method(requested_method)
+                    && config.allows_headers(requested_headers) =>
+            {
+                allow_origin
+            }
+            _ => {
+                tracing::warn!(origin, requested_method, "Rejected CORS preflight");
+                return Response::builder()
+                    .status(StatusCode::FORBIDDEN)
+                    .header(
+                        header::VARY,
+                        "Origin, Access-Control-Request-Method, Access-Control-Request-Headers",
+                    )
+                    .body(Body::empty())
+                    .unwrap();
+            }
+        };
+
+        let mut response = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header(header::ACCESS_CONTROL_ALLOW_METHODS, config.join_methods())
+            .header(header::ACCESS_CONTROL_ALLOW_HEADERS, config.join_headers())
+            .header(header::ACCESS_CONTROL_MAX_AGE, config.max_age.to_string())
             .header(
-                header::ACCESS_CONTROL_ALLOW_METHODS,
-                "GET, POST, PUT, DELETE, PATCH, OPTIONS",
+                header::VARY,
+                "Origin, Access-Control-Request-Method, Access-Control-Request-Headers",
             )
-            .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "*")
-            .header(header::ACCESS_CONTROL_MAX_AGE, "86400")
-            .header(header::VARY, "Origin, Access-Control-Request-Method")
+            .body(Body::empty())
+            .unwrap();
+        config.insert_common_headers(response.headers_mut(), allow_origin);
+
+        return response;
+    }
+
+    let mut response = next.run(req).await;
+
+    // Add CORS headers to the response only for allowed origins
+    let headers = response.headers_mut();
+    if let Some(allow_origin) = allow_origin {
+        config.insert_common_headers(headers, allow_origin);
+    }
+    // Append so a Vary set by the handler is kept
+    headers.append(header::VARY, HeaderValue::from_static("Origin"));
+
+    response
+}
+
+// Splits a comma-separated list, dropping empty entries
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    const ALLOWED: &str = "https://app.bustleit.com";
+
+    fn config() -> CorsConfig {
+        CorsConfig {
+            allowed_origins: vec![ALLOWED.to_string()],
+            allowed_methods: vec![Method::GET, Method::POST],
+            allowed_headers: vec![header::AUTHORIZATION, header::CONTENT_TYPE],
+            allow_credentials: true,
+            max_age: DEFAULT_MAX_AGE,
+        }
+    }
+
+    async fn send(req: Request<Body>) -> Response<Body> {
+        let app = Router::new()
+            .route(
+                "/",
+                get(|| async { ([(header::VARY, "Accept-Encoding")], "ok") }),
+            )
+            .layer(middleware::from_fn_with_state(
+                Arc::new(config()),
+                cors_middleware,
+            ));
+        app.oneshot(req).await.unwrap()
+    }
+
+    fn preflight(method: &str, headers: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/")
+            .header(header::ORIGIN, ALLOWED)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, headers)
             .body(Body::empty())
             .unwrap()
-    } else {
-        let mut response = next.run(req).await;
-
-        // Add CORS headers to the response
-        let headers = response.headers_mut();
-        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin_value);
-        headers.insert(
-            header::ACCESS_CONTROL_ALLOW_METHODS,
-            HeaderValue::from_static("GET, POST, PUT, DELETE, PATCH, OPTIONS"),
-        );
-        headers.insert(
-            header::ACCESS_CONTROL_ALLOW_HEADERS,
-            HeaderValue::from_static("*"),
-        );
-        headers.insert(
-            header::VARY,
-            HeaderValue::from_static("Origin, Access-Control-Request-Method"),
-        );
-
-        response
+    }
+
+    #[tokio::test]
+    async fn allowed_origin_gets_cors_headers_and_keeps_vary() {
+        let req = Request::get("/")
+            .header(header::ORIGIN, ALLOWED)
+            .body(Body::empty())
+            .unwrap();
+        let response = send(req).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], ALLOWED);
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        let vary: Vec<_> = headers.get_all(header::VARY).iter().collect();
+        assert_eq!(vary, ["Accept-Encoding", "Origin"]);
+    }
+
+    #[tokio::test]
+    async fn disallowed_origin_gets_no_cors_headers() {
+        let req = Request::get("/")
+            .header(header::ORIGIN, "https://evil.example")
+            .body(Body::empty())
+            .unwrap();
+        let response = send(req).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+        assert!(headers
+            .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn preflight_is_allowed() {
+        let response = send(preflight("POST", "Authorization, Content-Type")).await;
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], ALLOWED);
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET, POST");
+    }
+
+    #[tokio::test]
+    async fn preflight_rejects_disallowed_method() {
+        let response = send(preflight("DELETE", "content-type")).await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn preflight_rejects_disallowed_headers() {
+        let response = send(preflight("GET", "authorization, x-custom")).await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+
+    // The only test that touches the CORS_* variables, so it can't race another test
+    #[test]
+    fn from_env_rejects_wildcard_with_credentials() {
+        env::set_var("CORS_ALLOWED_ORIGINS", "*");
+        env::set_var("CORS_ALLOW_CREDENTIALS", "true");
+        let result = CorsConfig::from_env();
+        env::remove_var("CORS_ALLOWED_ORIGINS");
+        env::remove_var("CORS_ALLOW_CREDENTIALS");
+
+        assert!(matches!(result, Err(Error::Validation(_))));
     }
 }
@@ -1,2 +1,4 @@
 pub mod cors;
+pub mod jwt;
+pub mod mw_access;
 pub mod mw_auth;
@@ -0,0 +1,146 @@
+use axum::{
+    extract::{RawPathParams, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use uuid::Uuid;
+
+use super::mw_auth::AuthUser;
+
+/// Scope that lets a caller act on any user's resources and satisfies every other scope
+pub const ADMIN_SCOPE: &str = "admin";
+/// Read every user's tasks (ML pipeline)
+pub const TASKS_READ_ALL_SCOPE: &str = "tasks:read:all";
+/// List and batch-read every user's profile (ML pipeline, admin tools)
+pub const PROFILES_ADMIN_SCOPE: &str = "profiles:admin";
+/// Reassign users between clusters (ML pipeline)
+pub const CLUSTERS_WRITE_SCOPE: &str = "clusters:write";
+
+/// Path parameter naming the user a route acts on
+const USER_ID_PARAM: &str = "user_id";
+
+/// Rejects requests whose `:user_id` path parameter is not the authenticated caller
+///
+/// Applied with `Router::route_layer` so path parameters are available; routes without a
+/// `:user_id` parameter pass through untouched. Callers holding [`ADMIN_SCOPE`] may act on
+/// any user.
+pub async fn require_path_user(
+    params: Option<RawPathParams>,
+    user: AuthUser,
+    req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let path_user = params.as_ref().and_then(|params| {
+        params
+            .iter()
+            .find(|(key, _)| *key == USER_ID_PARAM)
+            .map(|(_, value)| value)
+    });
+
+    let Some(path_user) = path_user else {
+        return Ok(next.run(req).await);
+    };
+
+    let path_user = Uuid::parse_str(path_user).map_err(|_| {
+        tracing::warn!(user_id = %path_user, "Invalid user ID in path");
+        StatusCode::BAD_REQUEST
+    })?;
+
+    if user.user_id() == Some(path_user) || user.has_scope(ADMIN_SCOPE) {
+        Ok(next.run(req).await)
+    } else {
+        tracing::warn!(
+            subject = %user.subject,
+            user_id = %path_user,
+            "Caller is not allowed to access this user"
+        );
+        Err(StatusCode::FORBIDDEN)
+    }
+}
+
+/// Rejects callers that lack `scope` (or [`ADMIN_SCOPE`])
+///
+/// Applied per route, e.g.
+/// `get(handler).route_layer(middleware::from_fn_with_state(TASKS_READ_ALL_SCOPE, require_scope))`
+pub async fn require_scope(
+    State(scope): State<&'static str>,
+    user: AuthUser,
+    req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    if user.has_scope(scope) || user.has_scope(ADMIN_SCOPE) {
+        Ok(next.run(req).await)
+    } else {
+        tracing::warn!(subject = %user.subject, scope, "Caller is missing required scope");
+        Err(StatusCode::FORBIDDEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, middleware, routing::get, Extension, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    const CALLER: Uuid = Uuid::from_u128(0x1111);
+    const OTHER_USER: Uuid = Uuid::from_u128(0x2222);
+
+    fn caller(scopes: &[&str]) -> AuthUser {
+        AuthUser {
+            subject: CALLER.to_string(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    async fn send(user: AuthUser, uri: &str) -> StatusCode {
+        let app = Router::new()
+            .route("/v1/user/:user_id/schedule", get(|| async { "ok" }))
+            .route("/v1/tasks", get(|| async { "ok" }))
+            .route_layer(middleware::from_fn(require_path_user))
+            .layer(Extension(user));
+
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        app.oneshot(req).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn require_path_user_allows_own_id() {
+        let status = send(caller(&[]), &format!("/v1/user/{CALLER}/schedule")).await;
+
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn require_path_user_forbids_other_id() {
+        let status = send(caller(&[]), &format!("/v1/user/{OTHER_USER}/schedule")).await;
+
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn require_path_user_lets_admin_through() {
+        let status = send(
+            caller(&[ADMIN_SCOPE]),
+            &format!("/v1/user/{OTHER_USER}/schedule"),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn require_path_user_rejects_non_uuid() {
+        let status = send(caller(&[ADMIN_SCOPE]), "/v1/user/not-a-uuid/schedule").await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn require_path_user_ignores_routes_without_user_id() {
+        let status = send(caller(&[]), "/v1/tasks").await;
+
+        assert_eq!(status, StatusCode::OK);
+    }
+}
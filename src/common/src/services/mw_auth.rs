@@ -1,25 +1,46 @@
-use std::env;
+use std::sync::Arc;
 
 use axum::{
-    extract::Request,
-    http::{header, Method, StatusCode},
+    async_trait,
+    extract::{FromRequestParts, Request, State},
+    http::{header, request::Parts, Method, StatusCode},
     middleware::Next,
     response::Response,
 };
 
-const TEST_API_TOKEN: &str = "TEST_API_TOKEN";
+use uuid::Uuid;
 
-pub async fn auth(req: Request, next: Next) -> Result<Response, StatusCode> {
+use super::jwt::JwtVerifier;
+
+/// The authenticated caller, inserted into request extensions by [`auth`]
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    /// The token's `sub` claim
+    pub subject: String,
+    pub scopes: Vec<String>,
+}
+
+impl AuthUser {
+    /// The subject as a user ID, if the token was issued to an end user
+    pub fn user_id(&self) -> Option<Uuid> {
+        Uuid::parse_str(&self.subject).ok()
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+pub async fn auth(
+    State(verifier): State<Arc<JwtVerifier>>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
     // Skip auth for OPTIONS requests
     if req.method() == Method::OPTIONS {
         return Ok(next.run(req).await);
     }
 
-    let valid_token = env::var(TEST_API_TOKEN).map_err(|_| {
-        tracing::error!("API_AUTH_TOKEN not configuired");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-
     let auth_header = req
         .headers()
         .get(header::AUTHORIZATION)
@@ -34,15 +55,144 @@ pub async fn auth(req: Request, next: Next) -> Result<Response, StatusCode> {
     };
 
     // Makes sure auth header has the correct bearer token format
-    if let Some(token) = auth_header.strip_prefix("Bearer ") {
-        if token == valid_token {
-            Ok(next.run(req).await)
-        } else {
-            tracing::warn!("Invalid token provided");
-            Err(StatusCode::UNAUTHORIZED)
-        }
+    let token = if let Some(token) = auth_header.strip_prefix("Bearer ") {
+        token
     } else {
         tracing::warn!("Invalid Authorization header format");
-        Err(StatusCode::UNAUTHORIZED)
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+
+    let claims = verifier.verify(token).map_err(|e| {
+        tracing::warn!(error = %e, "Invalid token provided");
+        StatusCode::UNAUTHORIZED
+    })?;
+
+    let user = AuthUser {
+        subject: claims.sub,
+        scopes: claims
+            .scope
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::to_string)
+            .collect(),
+    };
+    req.extensions_mut().insert(user);
+
+    Ok(next.run(req).await)
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthUser
+where
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<AuthUser>()
+            .cloned()
+            .ok_or(StatusCode::UNAUTHORIZED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{to_bytes, Body},
+        middleware,
+        routing::get,
+        Router,
+    };
+    use jsonwebtoken::{encode, get_current_timestamp, Algorithm, EncodingKey, Header};
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    const SECRET: &str = "test-secret";
+    const USER_ID: Uuid = Uuid::from_u128(0x1111);
+
+    fn token(scope: Option<&str>) -> String {
+        let claims = json!({
+            "sub": USER_ID.to_string(),
+            "exp": get_current_timestamp() + 3600,
+            "scope": scope,
+        });
+        encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(SECRET.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    async fn send(method: Method, authorization: Option<&str>) -> (StatusCode, String) {
+        let verifier = JwtVerifier::new(Some(SECRET), None, None, None).unwrap();
+        let app = Router::new()
+            .route(
+                "/",
+                get(|user: AuthUser| async move {
+                    format!("{:?} [{}]", user.user_id(), user.scopes.join(","))
+                })
+                .options(|| async { "preflight" }),
+            )
+            .layer(middleware::from_fn_with_state(Arc::new(verifier), auth));
+
+        let mut req = Request::builder().method(method).uri("/");
+        if let Some(authorization) = authorization {
+            req = req.header(header::AUTHORIZATION, authorization);
+        }
+        let response = app.oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn auth_exposes_caller_and_scopes() {
+        let bearer = format!("Bearer {}", token(Some("admin  tasks:read")));
+
+        let (status, body) = send(Method::GET, Some(&bearer)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, format!("Some({USER_ID}) [admin,tasks:read]"));
+    }
+
+    #[tokio::test]
+    async fn auth_defaults_to_no_scopes() {
+        let bearer = format!("Bearer {}", token(None));
+
+        let (status, body) = send(Method::GET, Some(&bearer)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, format!("Some({USER_ID}) []"));
+    }
+
+    #[tokio::test]
+    async fn auth_rejects_missing_header() {
+        let (status, _) = send(Method::GET, None).await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn auth_rejects_malformed_header() {
+        let basic = format!("Basic {}", token(None));
+        for authorization in [basic.as_str(), "Bearer", "Bearer not-a-token"] {
+            let (status, _) = send(Method::GET, Some(authorization)).await;
+
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{authorization}");
+        }
+    }
+
+    #[tokio::test]
+    async fn auth_skips_options() {
+        let (status, body) = send(Method::OPTIONS, None).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "preflight");
     }
 }
@@ -0,0 +1,89 @@
+use sqlx::PgPool;
+use std::{env, process::ExitCode};
+
+use common::{
+    database::{
+        create_pool,
+        migrations::{self, MIGRATOR},
+        DatabaseConfig,
+    },
+    error::{Error, Result},
+};
+
+const USAGE: &str = "Usage: migrate <up | down [target_version] | status>";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .init();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match run(&args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            tracing::error!(error = %e, "Migration command failed");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: &[String]) -> Result<()> {
+    let command = args.first().map(String::as_str);
+    let pool = create_pool(DatabaseConfig::new()?).await?;
+
+    match command {
+        Some("up") => {
+            migrations::run_migrations(&pool).await?;
+            report_version(&pool).await
+        }
+        Some("down") => {
+            // Without an explicit target, only the latest migration is reverted
+            let target = match args.get(1) {
+                Some(target) => target
+                    .parse::<i64>()
+                    .map_err(|_| Error::validation("Target version must be an integer"))?,
+                None => {
+                    let applied = migrations::applied_versions(&pool).await?;
+                    applied.iter().rev().nth(1).copied().unwrap_or(0)
+                }
+            };
+
+            migrations::undo_migrations(&pool, target).await?;
+            report_version(&pool).await
+        }
+        Some("status") => {
+            let applied = migrations::applied_versions(&pool).await?;
+
+            for migration in MIGRATOR
+                .iter()
+                .filter(|m| m.migration_type.is_up_migration())
+            {
+                let state = if applied.contains(&migration.version) {
+                    "applied"
+                } else {
+                    "pending"
+                };
+                tracing::info!(
+                    version = migration.version,
+                    description = %migration.description,
+                    state,
+                    "Migration"
+                );
+            }
+
+            report_version(&pool).await
+        }
+        _ => Err(Error::validation(USAGE)),
+    }
+}
+
+async fn report_version(pool: &PgPool) -> Result<()> {
+    match migrations::current_version(pool).await? {
+        Some(version) => tracing::info!(version, "Current schema version"),
+        None => tracing::info!("No migrations applied"),
+    }
+    Ok(())
+}
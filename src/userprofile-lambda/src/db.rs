@@ -1,36 +1,101 @@
-use sqlx::{postgres::PgRow, PgPool, Row};
-use tracing::info;
+use async_stream::try_stream;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use futures_util::{Stream, TryStreamExt};
+use sqlx::{
+    postgres::{PgArguments, PgRow},
+    query::Query,
+    PgPool, Postgres, Row,
+};
 use uuid::Uuid;
 
-use crate::models::{RegisterUserPayload, UpdateClustersPayload};
+use crate::models::{
+    AcceptMode, AcceptedDay, PlannedTask, ProfileFilter, RegisterUserPayload, UpdateClustersPayload,
+};
 use common::{
-    database::DatabaseConfig,
+    database::tasks::{self, NewTask},
     error::{Error, Result},
     models::database as DB,
+    ndjson::{self, ExportCursor},
 };
 
+// WHERE clause shared by get_profiles_page and count_profiles; time windows are [after, before)
+const PROFILE_FILTER: &str = "($1::int IS NULL OR cluster = $1)
+                   AND ($2::text[] IS NULL OR preferences && $2)
+                   AND ($3::text[] IS NULL OR preferences @> $3)
+                   AND ($4::timestamptz IS NULL OR created_at >= $4)
+                   AND ($5::timestamptz IS NULL OR created_at < $5)
+                   AND ($6::timestamptz IS NULL OR updated_at >= $6)
+                   AND ($7::timestamptz IS NULL OR updated_at < $7)";
+
+#[derive(Clone)]
 pub struct ProfileDb {
     pool: PgPool,
 }
 
 impl ProfileDb {
-    pub async fn new() -> Result<Self> {
-        let config = DatabaseConfig::new()?;
-        let pool = common::database::create_pool(config).await?;
-
-        Ok(Self { pool })
+    /// Wraps a pool created once at startup and shared across invocations
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
     }
 
-    // TODO: Create a user profile
-    pub async fn create_user(&self, payload: RegisterUserPayload) -> Result<()> {
-        info!("Recieved payload: \n{:#?}", payload);
-        Ok(())
+    // Create a user along with their profile and routine
+    pub async fn create_user(&self, payload: RegisterUserPayload) -> Result<DB::Profile> {
+        let (work_start, work_end, sleep) = payload.routine.parse_times()?;
+        let timezone = payload.parse_timezone()?;
+        let scores = serde_json::to_value(&payload.scores)
+            .map_err(|e| Error::InternalServerError(e.to_string()))?;
+
+        let mut tx = self.pool.begin().await.map_err(Error::from)?;
+
+        let inserted = sqlx::query(
+            "INSERT INTO users (id)
+             VALUES ($1)
+             ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(payload.user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(Error::from)?;
+
+        if inserted.rows_affected() == 0 {
+            return Err(Error::conflict(format!("User {}", payload.user_id)));
+        }
+
+        let row = sqlx::query(
+            "INSERT INTO profiles (user_id, personality_scores, preferences, timezone)
+             VALUES ($1, $2, $3, $4)
+             RETURNING user_id, cluster, preferences, personality_scores, timezone, created_at, updated_at",
+        )
+        .bind(payload.user_id)
+        .bind(scores)
+        .bind(&payload.preferences)
+        .bind(timezone.name())
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(Error::from)?;
+
+        sqlx::query(
+            "INSERT INTO routines (user_id, work_start_time, work_end_time, sleep_time)
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(payload.user_id)
+        .bind(work_start)
+        .bind(work_end)
+        .bind(sleep)
+        .execute(&mut *tx)
+        .await
+        .map_err(Error::from)?;
+
+        tx.commit().await.map_err(Error::from)?;
+
+        Self::map_profile_row(row)
     }
 
     // Get single user profile
     pub async fn get_profile(&self, user_id: Uuid) -> Result<DB::Profile> {
         let row = sqlx::query(
-            "SELECT user_id, cluster, preferences, personality_scores, created_at, updated_at
+            "SELECT user_id, cluster, preferences, personality_scores, timezone, created_at, updated_at
                  FROM profiles
                  WHERE user_id = $1",
         )
@@ -50,7 +115,7 @@ impl ProfileDb {
     // Get multiple user profiles
     pub async fn get_profiles(&self, user_ids: &[Uuid]) -> Result<Vec<DB::Profile>> {
         let rows = sqlx::query(
-            "SELECT user_id, cluster, preferences, personality_scores, created_at, updated_at
+            "SELECT user_id, cluster, preferences, personality_scores, timezone, created_at, updated_at
                  FROM profiles
                  WHERE user_id = ANY($1)",
         )
@@ -67,20 +132,28 @@ impl ProfileDb {
         Ok(profiles)
     }
 
-    pub async fn get_profiles_by_cluster(&self, cluster: i32) -> Result<Vec<DB::Profile>> {
-        let rows = sqlx::query(
-            "SELECT user_id, cluster, preferences, personality_scores, created_at, updated_at
+    // Get a page of profiles matching `filter`, ordered by user_id, starting after `after`
+    pub async fn get_profiles_page(
+        &self,
+        filter: &ProfileFilter,
+        after: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<DB::Profile>> {
+        let query = format!(
+            "SELECT user_id, cluster, preferences, personality_scores, timezone, created_at, updated_at
                  FROM profiles
-                 WHERE cluster = $1",
-        )
-        .bind(cluster)
-        .fetch_all(&self.pool)
-        .await
-        .map_err(Error::Database)?;
+                 WHERE {PROFILE_FILTER}
+                   AND ($8::uuid IS NULL OR user_id > $8)
+                 ORDER BY user_id
+                 LIMIT $9"
+        );
 
-        if rows.is_empty() {
-            return Err(Error::not_found(cluster.to_string()));
-        }
+        let rows = Self::bind_profile_filter(sqlx::query(&query), filter)
+            .bind(after)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(Error::Database)?;
 
         let profiles = rows
             .into_iter()
@@ -90,25 +163,272 @@ impl ProfileDb {
         Ok(profiles)
     }
 
-    pub async fn get_all_users(&self) -> Result<Vec<DB::Profile>> {
-        let rows = sqlx::query(
-            "SELECT user_id, cluster, preferences, personality_scores, created_at, updated_at
-                 FROM profiles",
+    // Count the profiles matching `filter`, across all pages
+    pub async fn count_profiles(&self, filter: &ProfileFilter) -> Result<i64> {
+        let query = format!("SELECT count(*) FROM profiles WHERE {PROFILE_FILTER}");
+
+        let row = Self::bind_profile_filter(sqlx::query(&query), filter)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(Error::Database)?;
+
+        Ok(row.get(0))
+    }
+
+    // Binds $1-$7 of PROFILE_FILTER
+    fn bind_profile_filter<'q>(
+        query: Query<'q, Postgres, PgArguments>,
+        filter: &'q ProfileFilter,
+    ) -> Query<'q, Postgres, PgArguments> {
+        query
+            .bind(filter.cluster)
+            .bind(&filter.preferences_any)
+            .bind(&filter.preferences_all)
+            .bind(filter.created_after)
+            .bind(filter.created_before)
+            .bind(filter.updated_after)
+            .bind(filter.updated_before)
+    }
+
+    // Stream profiles (optionally only those updated at or after `since`) for export,
+    // ordered by (updated_at, user_id), resumed after `after` and at most `limit` rows.
+    // Reading starts ndjson::SINCE_OVERLAP earlier to catch late commits, so rows can
+    // repeat across exports
+    pub fn export_profiles(
+        &self,
+        since: Option<DateTime<Utc>>,
+        after: Option<&ExportCursor>,
+        limit: i64,
+    ) -> impl Stream<Item = Result<DB::Profile>> + Send + 'static {
+        let pool = self.pool.clone();
+        let after = after.map(|cursor| (cursor.updated_at, cursor.id));
+        try_stream! {
+            let mut rows = sqlx::query(
+                "SELECT user_id, cluster, preferences, personality_scores, timezone, created_at, updated_at
+                     FROM profiles
+                     WHERE ($1::timestamptz IS NULL OR updated_at >= $1)
+                       AND ($2::timestamptz IS NULL OR (updated_at, user_id) > ($2, $3))
+                     ORDER BY updated_at, user_id
+                     LIMIT $4",
+            )
+            .bind(ndjson::overlapped_since(since))
+            .bind(after.map(|(updated_at, _)| updated_at))
+            .bind(after.map(|(_, id)| id))
+            .bind(limit)
+            .fetch(&pool);
+
+            while let Some(row) = rows.try_next().await.map_err(Error::Database)? {
+                yield Self::map_profile_row(row)?;
+            }
+        }
+    }
+
+    // Get a user's routine, if one has been stored
+    pub async fn get_routine(&self, user_id: Uuid) -> Result<Option<DB::Routine>> {
+        let row = sqlx::query(
+            "SELECT user_id, work_start_time, work_end_time, sleep_time, created_at, updated_at
+                 FROM routines
+                 WHERE user_id = $1",
         )
-        .fetch_all(&self.pool)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
         .await
         .map_err(Error::Database)?;
 
-        if rows.is_empty() {
-            return Err(Error::not_found("No profiles found".to_string()));
+        row.map(Self::map_routine_row).transpose()
+    }
+
+    // Create or replace a user's routine
+    pub async fn upsert_routine(
+        &self,
+        user_id: Uuid,
+        work_start: NaiveTime,
+        work_end: NaiveTime,
+        sleep: NaiveTime,
+    ) -> Result<DB::Routine> {
+        let row = sqlx::query(
+            "INSERT INTO routines (user_id, work_start_time, work_end_time, sleep_time)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (user_id) DO UPDATE SET
+                     work_start_time = EXCLUDED.work_start_time,
+                     work_end_time = EXCLUDED.work_end_time,
+                     sleep_time = EXCLUDED.sleep_time,
+                     updated_at = CURRENT_TIMESTAMP
+                 RETURNING user_id, work_start_time, work_end_time, sleep_time, created_at, updated_at",
+        )
+        .bind(user_id)
+        .bind(work_start)
+        .bind(work_end)
+        .bind(sleep)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db_err) if db_err.is_foreign_key_violation() => {
+                Error::not_found(user_id.to_string())
+            }
+            e => Error::Database(e),
+        })?;
+
+        Self::map_routine_row(row)
+    }
+
+    // Get a user's stored ranking, if one exists
+    pub async fn get_ranking(&self, user_id: Uuid) -> Result<Option<DB::Ranking>> {
+        let row = sqlx::query(
+            "SELECT user_id, ranked_preferences, ranked_categories, ranked_at, created_at, updated_at
+                 FROM rankings
+                 WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(Error::Database)?;
+
+        row.map(Self::map_ranking_row).transpose()
+    }
+
+    // Create or replace a user's ranking
+    pub async fn upsert_ranking(
+        &self,
+        user_id: Uuid,
+        preferences: &[DB::RankedItem],
+        categories: &[DB::RankedItem],
+    ) -> Result<DB::Ranking> {
+        let preferences = serde_json::to_value(preferences)
+            .map_err(|e| Error::InternalServerError(e.to_string()))?;
+        let categories = serde_json::to_value(categories)
+            .map_err(|e| Error::InternalServerError(e.to_string()))?;
+
+        let row = sqlx::query(
+            "INSERT INTO rankings (user_id, ranked_preferences, ranked_categories)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT (user_id) DO UPDATE SET
+                     ranked_preferences = EXCLUDED.ranked_preferences,
+                     ranked_categories = EXCLUDED.ranked_categories,
+                     ranked_at = CURRENT_TIMESTAMP,
+                     updated_at = CURRENT_TIMESTAMP
+                 RETURNING user_id, ranked_preferences, ranked_categories, ranked_at, created_at, updated_at",
+        )
+        .bind(user_id)
+        .bind(preferences)
+        .bind(categories)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(Error::Database)?;
+
+        Self::map_ranking_row(row)
+    }
+
+    // Write accepted recommendation days into the user's schedule, all or nothing
+    // Tasks go through the same validation and overlap checks as the tasks API
+    pub async fn accept_schedule(
+        &self,
+        user_id: Uuid,
+        days: &[(NaiveDate, Vec<PlannedTask>)],
+        mode: AcceptMode,
+        tz: Tz,
+    ) -> Result<Vec<AcceptedDay>> {
+        let mut tx = self.pool.begin().await.map_err(Error::from)?;
+
+        tasks::lock_user_tasks(&mut tx, user_id).await?;
+
+        // Clear every accepted day before inserting, so a task running past midnight
+        // isn't removed again by the next day's replace
+        let mut removed = Vec::with_capacity(days.len());
+        for (date, _) in days {
+            // Every task touching the day, including ones running over from the day before
+            let tasks_removed = match mode {
+                AcceptMode::Replace => {
+                    let (day_start, day_end) = tasks::day_bounds(*date, tz);
+                    sqlx::query(
+                        "DELETE FROM tasks
+                         WHERE user_id = $1 AND start_time < $3 AND end_time > $2",
+                    )
+                    .bind(user_id)
+                    .bind(day_start)
+                    .bind(day_end)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(Error::from)?
+                    .rows_affected()
+                }
+                AcceptMode::Merge => 0,
+            };
+            removed.push(tasks_removed);
         }
 
-        let profiles = rows
-            .into_iter()
-            .map(Self::map_profile_row)
-            .collect::<Result<Vec<_>>>()?;
+        let mut accepted = Vec::with_capacity(days.len());
+        for ((date, planned), tasks_removed) in days.iter().zip(removed) {
+            let mut task_ids = Vec::new();
+            let mut tasks_skipped = Vec::new();
+            for recommended in planned {
+                let task = NewTask {
+                    schedule_date: recommended.date,
+                    end_date: recommended.end_date,
+                    name: &recommended.name,
+                    category: &recommended.category,
+                    start_time: recommended.start_time,
+                    end_time: recommended.end_time,
+                    ical_uid: None,
+                };
+                task.validate()?;
 
-        Ok(profiles)
+                // Replace can still collide with recurring occurrences or the next day
+                match tasks::check_overlaps(
+                    &mut tx,
+                    user_id,
+                    task.start_time,
+                    task.end_time,
+                    None,
+                    tz,
+                )
+                .await
+                {
+                    Ok(()) => {}
+                    Err(Error::Overlap(_)) => {
+                        tasks_skipped.push(task.name.to_string());
+                        continue;
+                    }
+                    Err(error) => return Err(error),
+                }
+
+                let created = tasks::insert_task(&mut tx, user_id, &task).await?;
+                task_ids.push(created.id);
+            }
+
+            accepted.push(AcceptedDay {
+                date: date.format("%Y-%m-%d").to_string(),
+                task_ids,
+                tasks_removed,
+                tasks_skipped,
+            });
+        }
+
+        tx.commit().await.map_err(Error::from)?;
+
+        Ok(accepted)
+    }
+
+    fn map_ranking_row(row: PgRow) -> Result<DB::Ranking> {
+        Ok(DB::Ranking {
+            user_id: row.get("user_id"),
+            ranked_preferences: row.get("ranked_preferences"),
+            ranked_categories: row.get("ranked_categories"),
+            ranked_at: row.get("ranked_at"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        })
+    }
+
+    fn map_routine_row(row: PgRow) -> Result<DB::Routine> {
+        Ok(DB::Routine {
+            user_id: row.get("user_id"),
+            work_start_time: row.get("work_start_time"),
+            work_end_time: row.get("work_end_time"),
+            sleep_time: row.get("sleep_time"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        })
     }
 
     fn map_profile_row(row: PgRow) -> Result<DB::Profile> {
@@ -117,11 +437,50 @@ impl ProfileDb {
             cluster: row.get("cluster"),
             preferences: row.get("preferences"),
             personality_scores: row.get("personality_scores"),
+            timezone: row.get("timezone"),
             created_at: row.get("created_at"),
             updated_at: row.get("updated_at"),
         })
     }
 
+    // Set a user's timezone and return the updated profile
+    pub async fn update_timezone(&self, user_id: Uuid, timezone: Tz) -> Result<DB::Profile> {
+        let row = sqlx::query(
+            "UPDATE profiles
+                 SET timezone = $1,
+                     updated_at = CURRENT_TIMESTAMP
+                 WHERE user_id = $2
+                 RETURNING user_id, cluster, preferences, personality_scores, timezone, created_at, updated_at",
+        )
+        .bind(timezone.name())
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(Error::Database)?
+        .ok_or_else(|| Error::not_found(user_id.to_string()))?;
+
+        Self::map_profile_row(row)
+    }
+
+    // Set a single user's cluster and return the updated profile
+    pub async fn update_cluster(&self, user_id: Uuid, cluster: i32) -> Result<DB::Profile> {
+        let row = sqlx::query(
+            "UPDATE profiles
+                 SET cluster = $1,
+                     updated_at = CURRENT_TIMESTAMP
+                 WHERE user_id = $2
+                 RETURNING user_id, cluster, preferences, personality_scores, timezone, created_at, updated_at",
+        )
+        .bind(cluster)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(Error::Database)?
+        .ok_or_else(|| Error::not_found(user_id.to_string()))?;
+
+        Self::map_profile_row(row)
+    }
+
     pub async fn update_clusters_batch(&self, payload: UpdateClustersPayload) -> Result<()> {
         let mut tx = self.pool.begin().await.map_err(Error::from)?;
 
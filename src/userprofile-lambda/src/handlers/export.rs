@@ -0,0 +1,119 @@
+use axum::{
+    extract::{Query, State},
+    response::Response,
+};
+
+use crate::db::ProfileDb;
+use crate::models::ExportQuery;
+use common::{
+    error::Result,
+    ndjson::{self, ExportCursor},
+};
+
+/// GET: /v1/export/profiles.ndjson[?since={timestamp}][&cursor={cursor}]
+///
+/// Exports profiles as newline-delimited JSON, one stored profile per line
+///
+/// Query Parameters:
+///   - since (optional): RFC 3339 timestamp, only exports profiles updated at or after it
+///   - cursor (optional): `x-next-cursor` header of the previous page
+///
+/// Returns:
+///   - 200: `application/x-ndjson`, ordered by `updated_at` so the last line's
+///     `updated_at` can be passed as the next `since`. The 15 minutes before `since`
+///     are re-read to catch late commits, so profiles may repeat and should be
+///     upserted by `user_id`. At most `ndjson::MAX_EXPORT_ROWS` profiles are returned;
+///     while more remain, `x-next-cursor` is set and should be requested with the same
+///     `since`
+///   - 400: If since or cursor is invalid
+///   - 500: For server errors
+pub async fn export_profiles(
+    State(db): State<ProfileDb>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response> {
+    let after = query
+        .cursor
+        .as_deref()
+        .map(ExportCursor::decode)
+        .transpose()?;
+
+    // Fetch one extra profile to know whether there's another page
+    let limit = ndjson::MAX_EXPORT_ROWS;
+    let rows = db.export_profiles(query.since, after.as_ref(), limit + 1);
+    ndjson::page(rows, limit, |profile| ExportCursor {
+        updated_at: profile.updated_at,
+        id: profile.user_id,
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use serde_json::Value;
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use common::database::migrations::MIGRATOR;
+
+    async fn export_page(
+        db: &ProfileDb,
+        after: Option<&ExportCursor>,
+    ) -> (Vec<Uuid>, Option<String>) {
+        let rows = db.export_profiles(None, after, 3);
+        let response = ndjson::page(rows, 2, |profile| ExportCursor {
+            updated_at: profile.updated_at,
+            id: profile.user_id,
+        })
+        .await
+        .unwrap();
+
+        let next_cursor = response
+            .headers()
+            .get(ndjson::NEXT_CURSOR_HEADER)
+            .map(|value| value.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let ids = body
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let profile: Value = serde_json::from_slice(line).unwrap();
+                profile["user_id"].as_str().unwrap().parse().unwrap()
+            })
+            .collect();
+
+        (ids, next_cursor)
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    async fn export_pages_with_cursor(pool: PgPool) {
+        let mut user_ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        for user_id in &user_ids {
+            sqlx::query("INSERT INTO users (id) VALUES ($1)")
+                .bind(user_id)
+                .execute(&pool)
+                .await
+                .unwrap();
+            // Same updated_at for all, so the page boundary falls on the id tiebreak
+            sqlx::query(
+                "INSERT INTO profiles (user_id, preferences, personality_scores, updated_at)
+                 VALUES ($1, ARRAY['music'], '{}', '2024-11-25T00:00:00Z')",
+            )
+            .bind(user_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        user_ids.sort();
+        let db = ProfileDb::new(pool);
+
+        let (first, next_cursor) = export_page(&db, None).await;
+        assert_eq!(first, user_ids[..2]);
+
+        let after = ExportCursor::decode(&next_cursor.unwrap()).unwrap();
+        let (second, next_cursor) = export_page(&db, Some(&after)).await;
+        assert_eq!(second, user_ids[2..]);
+        assert!(next_cursor.is_none());
+    }
+}
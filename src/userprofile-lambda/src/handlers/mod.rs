@@ -1,4 +1,6 @@
+pub mod export;
 pub mod profile;
 pub mod profiles;
 pub mod recommend;
 pub mod register;
+pub mod routine;
@@ -1,20 +1,54 @@
-use axum::{extract::Path, Json};
+use axum::{
+    extract::{Path, State},
+    Json,
+};
 use uuid::Uuid;
 
-use crate::{db::ProfileDb, models::UserProfile};
-use common::error::Result;
+use crate::{
+    db::ProfileDb,
+    models::{TimezoneData, UserProfile},
+};
+use common::{error::Result, models::database as DB};
 
-/// GET: /v1/user/profile/:id
+/// GET: /v1/user/profile/:user_id
 ///
 /// Returns a single user profile by ID
 ///
 /// Example:
 ///   - /v1/user/profile/123e4567-e89b-12d3-a456-426614174000
-pub async fn get_profile(Path(user_id): Path<Uuid>) -> Result<Json<UserProfile>> {
-    let db = ProfileDb::new().await?;
-
+pub async fn get_profile(
+    State(db): State<ProfileDb>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<UserProfile>> {
     let profile_db = db.get_profile(user_id).await?;
     let profile = UserProfile::from(&profile_db);
 
     Ok(Json(profile))
 }
+
+/// PUT: /v1/user/:user_id/timezone
+///
+/// Sets the IANA timezone used to interpret the user's task times
+///
+/// Request Body:
+/// ```json
+/// {
+///     "timezone": "Europe/London"
+/// }
+/// ```
+///
+/// Returns:
+///   - 200: The updated profile
+///   - 400: If the timezone is not a known IANA name
+///   - 404: If the user does not exist
+///   - 500: For server errors
+pub async fn put_timezone(
+    State(db): State<ProfileDb>,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<TimezoneData>,
+) -> Result<Json<UserProfile>> {
+    let timezone = DB::parse_timezone(&payload.timezone)?;
+    let profile_db = db.update_timezone(user_id, timezone).await?;
+
+    Ok(Json(UserProfile::from(&profile_db)))
+}
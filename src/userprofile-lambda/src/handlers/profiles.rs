@@ -1,7 +1,12 @@
-use axum::{extract::Query, Json};
+use axum::{
+    extract::{Query, State},
+    Json,
+};
 
 use crate::db::ProfileDb;
-use crate::models::{convert_profiles, UserProfile, UserProfilesBatchRequest, UserProfilesQuery};
+use crate::models::{
+    convert_profiles, UserProfile, UserProfilesBatchRequest, UserProfilesPage, UserProfilesQuery,
+};
 use common::error::{Error, Result};
 
 /// POST: /v1/user/profiles/batch
@@ -19,53 +24,63 @@ use common::error::{Error, Result};
 /// - 400: If request body is invalid
 /// - 500: For server errors
 pub async fn get_batch(
+    State(db): State<ProfileDb>,
     Json(payload): Json<UserProfilesBatchRequest>,
 ) -> Result<Json<Vec<UserProfile>>> {
     if payload.user_ids.is_empty() {
         return Err(Error::validation("At least one user ID must be provided"));
     }
 
-    let db = ProfileDb::new().await?;
     let profiles_db = db.get_profiles(&payload.user_ids).await?;
     let respoonse = convert_profiles(profiles_db);
 
     Ok(Json(respoonse))
 }
 
-/// GET: /v1/user/profiles[?cluster={cluster_id}]
+/// GET: /v1/user/profiles
 ///
-/// Returns all user profiles or filters by cluster if cluster query parameter is provided
+/// Returns a page of user profiles, ordered by user ID, optionally filtered
 ///
 /// Query Parameters:
+///   - limit (optional): Page size (1-500), defaults to 100
+///   - after (optional): `next_cursor` of the previous page
 ///   - cluster (optional): Integer value representing the cluster ID
+///   - preferences_any (optional): Comma separated, profiles with at least one of them
+///   - preferences_all (optional): Comma separated, profiles with all of them
+///   - created_after, created_before (optional): RFC 3339 window on creation time
+///   - updated_after, updated_before (optional): RFC 3339 window on last update
 ///
 /// Returns:
-///   - 200: Array of user profiles
-///   - 400: If cluster parameter is invalid
-///   - 404: If no profiles found
+///   - 200: A page of profiles, the `total` matching the filters and a `next_cursor`
+///   - 400: If a parameter is invalid
 ///   - 500: For server errors
 ///
 /// Examples:
-///   - /v1/user/profiles            ->  Returns all user profiles
-///   - /v1/user/profiles?cluster=3  ->  Returns profiles in cluster 3
+///   - /v1/user/profiles?cluster=3                     ->  First 100 profiles in cluster 3
+///   - /v1/user/profiles?preferences_all=music,reading ->  Profiles with both preferences
 pub async fn get_profiles(
+    State(db): State<ProfileDb>,
     Query(query): Query<UserProfilesQuery>,
-) -> Result<Json<Vec<UserProfile>>> {
-    let db = ProfileDb::new().await?;
+) -> Result<Json<UserProfilesPage>> {
+    let limit = query.limit()?;
+    let filter = query.filter()?;
 
-    let profiles_db = match query.cluster {
-        Some(cluster) => {
-            if cluster < 0 {
-                return Err(Error::validation(
-                    "Cluster ID must be a non-negative integer",
-                ));
-            }
-            db.get_profiles_by_cluster(cluster).await
-        }
-        None => db.get_all_users().await,
-    }?;
+    // Fetch one extra profile to know whether there's another page
+    let mut profiles_db = db
+        .get_profiles_page(&filter, query.after, limit + 1)
+        .await?;
+    let next_cursor = if profiles_db.len() as i64 > limit {
+        profiles_db.truncate(limit as usize);
+        profiles_db.last().map(|profile| profile.user_id)
+    } else {
+        None
+    };
 
-    let profiles = convert_profiles(profiles_db);
+    let total = db.count_profiles(&filter).await?;
 
-    Ok(Json(profiles))
+    Ok(Json(UserProfilesPage {
+        profiles: convert_profiles(profiles_db),
+        total,
+        next_cursor,
+    }))
 }
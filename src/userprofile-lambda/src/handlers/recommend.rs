@@ -1,23 +1,32 @@
-use axum::{extract::Path, Json};
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
 use chrono::NaiveTime;
 use uuid::Uuid;
 
 use crate::{
     db::ProfileDb,
     models::{
-        RequestClusterUser, RequestRankUser, RequestRecommend, ResponseRecommendDaily,
-        ResponseRecommendWeekly, UpdateClustersPayload,
+        AcceptRecommendationPayload, AcceptRecommendationResponse, DaySchedule, RankQuery,
+        RecommendPeriod, RequestClusterUser, RequestRankUser, RequestRecommend,
+        ResponseClusterUser, ResponseRankUser, ResponseRecommendDaily, ResponseRecommendWeekly,
+        UpdateClustersPayload, UserProfile, UserRanking,
     },
 };
-use common::error::{Error, Result};
+use common::{
+    error::{Error, Result},
+    models::database as DB,
+};
 
-// [TODO]
 // GET: /v1/cluster/:user_id
-#[allow(unused_variables)]
-pub async fn cluster_user(Path(user_id): Path<Uuid>) -> Result<()> {
-    let url = get_external_endpoint("/cluster");
+// Asks the external API which cluster the user belongs to and stores the result
+pub async fn cluster_user(
+    State(db): State<ProfileDb>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<UserProfile>> {
+    let url = get_external_endpoint("/cluster")?;
 
-    let db = ProfileDb::new().await?;
     let profile_data = db.get_profile(user_id).await?;
 
     let request_body = RequestClusterUser::new(
@@ -26,26 +35,46 @@ pub async fn cluster_user(Path(user_id): Path<Uuid>) -> Result<()> {
         profile_data.preferences,
     );
 
-    // TODO:
-    //      - Make the API request & Map to expected response
-    //      - Save data into DB
+    let response = make_api_request::<_, ResponseClusterUser>(url, &request_body).await?;
+
+    if response.user_id != user_id {
+        return Err(Error::InternalServerError(
+            "External API error: cluster returned for a different user".to_string(),
+        ));
+    }
+    if response.cluster < 0 {
+        return Err(Error::InternalServerError(
+            "External API error: invalid cluster number".to_string(),
+        ));
+    }
 
-    Ok(())
+    let profile_db = db.update_cluster(user_id, response.cluster).await?;
+
+    Ok(Json(UserProfile::from(&profile_db)))
 }
 
 // POST: /v1/user/cluster/update
-pub async fn update_cluser(Json(payload): Json<UpdateClustersPayload>) -> Result<()> {
-    let db = ProfileDb::new().await?;
+pub async fn update_cluser(
+    State(db): State<ProfileDb>,
+    Json(payload): Json<UpdateClustersPayload>,
+) -> Result<()> {
     db.update_clusters_batch(payload).await
 }
 
-// [TODO]
-// GET: /v1/rank/:user_id
-#[allow(unused_variables)]
-pub async fn rank_user(Path(user_id): Path<Uuid>) -> Result<()> {
-    let url = get_external_endpoint("/rank");
+// GET: /v1/rank/:user_id[?refresh=true]
+// Returns the stored ranking, querying the external API if none is stored or refresh is set
+pub async fn rank_user(
+    State(db): State<ProfileDb>,
+    Path(user_id): Path<Uuid>,
+    Query(query): Query<RankQuery>,
+) -> Result<Json<UserRanking>> {
+    if !query.refresh {
+        if let Some(ranking) = db.get_ranking(user_id).await? {
+            return Ok(Json(UserRanking::from(&ranking)));
+        }
+    }
 
-    let db = ProfileDb::new().await?;
+    let url = get_external_endpoint("/rank")?;
     let profile_data = db.get_profile(user_id).await?;
 
     let request_body = RequestRankUser::new(
@@ -55,20 +84,30 @@ pub async fn rank_user(Path(user_id): Path<Uuid>) -> Result<()> {
         profile_data.cluster,
     );
 
-    // TODO:
-    //      - Make the API request & Map to expected response
-    //      - Save data into DB
+    let response = make_api_request::<_, ResponseRankUser>(url, &request_body).await?;
+
+    if response.user_id != user_id {
+        return Err(Error::InternalServerError(
+            "External API error: ranking returned for a different user".to_string(),
+        ));
+    }
 
-    Ok(())
+    let ranking = db
+        .upsert_ranking(user_id, &response.preferences, &response.categories)
+        .await?;
+
+    Ok(Json(UserRanking::from(&ranking)))
 }
 
 // GET: /v1/recommend/:user_id
-pub async fn get_recommendation(Path(user_id): Path<Uuid>) -> Result<Json<ResponseRecommendDaily>> {
+pub async fn get_recommendation(
+    State(db): State<ProfileDb>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<ResponseRecommendDaily>> {
     let url = get_external_endpoint("/recommend_daily")?;
 
-    let db = ProfileDb::new().await?;
     let profile_data = db.get_profile(user_id).await?;
-    let times = UserTimes::default(); // TEMP: Hardcoded missing User Data for now
+    let times = UserTimes::for_user(&db, user_id).await?;
 
     let request_body = RequestRecommend::new(
         user_id,
@@ -87,13 +126,13 @@ pub async fn get_recommendation(Path(user_id): Path<Uuid>) -> Result<Json<Respon
 
 // GET /v1/recommend/:user_id/week
 pub async fn get_recommendation_week(
+    State(db): State<ProfileDb>,
     Path(user_id): Path<Uuid>,
 ) -> Result<Json<ResponseRecommendWeekly>> {
     let url = get_external_endpoint("/recommend_weekly")?;
 
-    let db = ProfileDb::new().await?;
     let profile_data = db.get_profile(user_id).await?;
-    let times = UserTimes::default(); // TEMP: Hardcoded missing User Data for now
+    let times = UserTimes::for_user(&db, user_id).await?;
 
     let request_body = RequestRecommend::new(
         user_id,
@@ -110,6 +149,98 @@ pub async fn get_recommendation_week(
     Ok(Json(response))
 }
 
+/// POST: /v1/recommend/:user_id/accept
+///
+/// Writes a recommended day or week into the user's schedule as tasks, in one
+/// transaction. Times are read in the user's timezone
+///
+/// Request Body:
+/// ```json
+/// {
+///     "mode": "merge",
+///     "period": "day",
+///     "days": [{ "date": "2024-11-25", "day": "Monday", "tasks": [...] }]
+/// }
+/// ```
+///   - mode: "merge" (default) keeps existing tasks and skips recommended ones
+///     overlapping them; "replace" removes the tasks touching each day first, then
+///     skips recommended ones overlapping recurring tasks or the next day
+///   - days: The recommendation to accept; fetched afresh for `period`
+///     ("day" or "week") when omitted
+///
+/// Returns:
+///   - 200: Per-day task IDs created, tasks removed and tasks skipped
+///   - 400: If a date, time, name or category in the recommendation is invalid
+///   - 404: If the user does not exist
+///   - 500: For server or external API errors
+pub async fn accept_recommendation(
+    State(db): State<ProfileDb>,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<AcceptRecommendationPayload>,
+) -> Result<Json<AcceptRecommendationResponse>> {
+    let profile_data = db.get_profile(user_id).await?;
+    let tz = profile_data.get_timezone();
+
+    let days = match payload.days {
+        Some(days) => days,
+        None => fetch_recommendation(&db, profile_data, payload.period).await?,
+    };
+    if days.is_empty() {
+        return Err(Error::validation("At least one day must be provided"));
+    }
+
+    let mut planned = days
+        .iter()
+        .map(|day| day.plan(tz))
+        .collect::<Result<Vec<_>>>()?;
+    planned.sort_by_key(|(date, _)| *date);
+
+    let accepted = db
+        .accept_schedule(user_id, &planned, payload.mode, tz)
+        .await?;
+
+    Ok(Json(AcceptRecommendationResponse {
+        user_id,
+        mode: payload.mode,
+        days: accepted,
+    }))
+}
+
+// Asks the external API for a fresh recommendation for the user
+async fn fetch_recommendation(
+    db: &ProfileDb,
+    profile_data: DB::Profile,
+    period: RecommendPeriod,
+) -> Result<Vec<DaySchedule>> {
+    let user_id = profile_data.user_id;
+    let times = UserTimes::for_user(db, user_id).await?;
+
+    let request_body = RequestRecommend::new(
+        user_id,
+        profile_data.get_typed_scores().unwrap_or_default(),
+        profile_data.preferences,
+        profile_data.cluster,
+        times.work_start,
+        times.work_end,
+        times.sleep,
+    );
+
+    let days = match period {
+        RecommendPeriod::Day => {
+            let url = get_external_endpoint("/recommend_daily")?;
+            let daily = make_api_request::<_, ResponseRecommendDaily>(url, &request_body).await?;
+            vec![DaySchedule::from(daily)]
+        }
+        RecommendPeriod::Week => {
+            let url = get_external_endpoint("/recommend_weekly")?;
+            let weekly = make_api_request::<_, ResponseRecommendWeekly>(url, &request_body).await?;
+            weekly.days.into_values().collect()
+        }
+    };
+
+    Ok(days)
+}
+
 // This function gets the url of the external api from env and arg
 fn get_external_endpoint(endpoint: &str) -> Result<String> {
     let uri_base =
@@ -144,7 +275,7 @@ where
         .map_err(|_| Error::InternalServerError("External API error".to_string()))
 }
 
-// TEMP: Until we have real user data
+// Routine times sent to the external API
 struct UserTimes {
     work_start: NaiveTime,
     work_end: NaiveTime,
@@ -152,16 +283,366 @@ struct UserTimes {
 }
 
 impl UserTimes {
-    // Convert time to numeric format (HHMM)
-    // fn to_numeric(&self, time: NaiveTime) -> i32 {
-    //     (time.hour() as i32 * 100) + time.minute() as i32
-    // }
+    // Uses the user's stored routine, falling back to the defaults if none is stored
+    async fn for_user(db: &ProfileDb, user_id: Uuid) -> Result<Self> {
+        let times = match db.get_routine(user_id).await? {
+            Some(routine) => Self {
+                work_start: routine.work_start_time,
+                work_end: routine.work_end_time,
+                sleep: routine.sleep_time,
+            },
+            None => Self::default(),
+        };
 
+        Ok(times)
+    }
+}
+
+impl Default for UserTimes {
     fn default() -> Self {
         Self {
-            work_start: NaiveTime::parse_from_str("09:00", "%H:%M").unwrap(),
-            work_end: NaiveTime::parse_from_str("16:30", "%H:%M").unwrap(),
-            sleep: NaiveTime::parse_from_str("22:00", "%H:%M").unwrap(),
+            work_start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            work_end: NaiveTime::from_hms_opt(16, 30, 0).unwrap(),
+            sleep: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+    use serde_json::{json, Value};
+    use sqlx::PgPool;
+    use std::sync::OnceLock;
+
+    use common::database::migrations::MIGRATOR;
+
+    // Users the mock external API misbehaves for
+    const MISMATCHED_USER: Uuid = Uuid::from_u128(0xbad1);
+    const NEGATIVE_CLUSTER_USER: Uuid = Uuid::from_u128(0xbad2);
+
+    const MOCK_CLUSTER: i32 = 4;
+
+    /// Serves the external API on a local port for the whole test run and points
+    /// EXTERNAL_API at it
+    fn mock_external_api() {
+        static STARTED: OnceLock<()> = OnceLock::new();
+        STARTED.get_or_init(|| {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.set_nonblocking(true).unwrap();
+            let base = format!("http://{}", listener.local_addr().unwrap());
+            std::env::set_var("EXTERNAL_API", base);
+
+            // Each #[sqlx::test] has its own runtime, so the server gets one that outlives them
+            std::thread::spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+                runtime.block_on(async move {
+                    let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                    axum::serve(listener, mock_router()).await.unwrap();
+                });
+            });
+        });
+    }
+
+    fn mock_router() -> Router {
+        Router::new()
+            .route("/cluster", post(mock_cluster))
+            .route("/rank", post(mock_rank))
+    }
+
+    async fn mock_cluster(Json(body): Json<Value>) -> Json<Value> {
+        let user_id: Uuid = serde_json::from_value(body["user_id"].clone()).unwrap();
+
+        let (user_id, cluster) = if user_id == MISMATCHED_USER {
+            (Uuid::new_v4(), MOCK_CLUSTER)
+        } else if user_id == NEGATIVE_CLUSTER_USER {
+            (user_id, -1)
+        } else {
+            (user_id, MOCK_CLUSTER)
+        };
+
+        Json(json!({ "user_id": user_id, "cluster": cluster }))
+    }
+
+    async fn mock_rank(Json(body): Json<Value>) -> Json<Value> {
+        let user_id: Uuid = serde_json::from_value(body["user_id"].clone()).unwrap();
+        let user_id = match user_id == MISMATCHED_USER {
+            true => Uuid::new_v4(),
+            false => user_id,
+        };
+
+        Json(json!({
+            "user_id": user_id,
+            "preferences": [{ "name": "music", "score": 0.9 }],
+            "categories": [{ "name": "Leisure", "score": 0.7 }],
+        }))
+    }
+
+    async fn insert_profile(pool: &PgPool, user_id: Uuid) {
+        sqlx::query("INSERT INTO users (id) VALUES ($1)")
+            .bind(user_id)
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO profiles (user_id, cluster, preferences, personality_scores)
+             VALUES ($1, 0, ARRAY['music'], '{}')",
+        )
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    async fn stored_cluster(pool: &PgPool, user_id: Uuid) -> i32 {
+        sqlx::query_scalar("SELECT cluster FROM profiles WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    async fn cluster_user_stores_cluster(pool: PgPool) {
+        mock_external_api();
+        let user_id = Uuid::new_v4();
+        insert_profile(&pool, user_id).await;
+
+        let Json(profile) = cluster_user(State(ProfileDb::new(pool.clone())), Path(user_id))
+            .await
+            .unwrap();
+
+        assert_eq!(profile.cluster, MOCK_CLUSTER);
+        assert_eq!(stored_cluster(&pool, user_id).await, MOCK_CLUSTER);
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    async fn cluster_user_rejects_mismatched_user(pool: PgPool) {
+        mock_external_api();
+        insert_profile(&pool, MISMATCHED_USER).await;
+
+        let result = cluster_user(State(ProfileDb::new(pool.clone())), Path(MISMATCHED_USER)).await;
+
+        assert!(matches!(result, Err(Error::InternalServerError(_))));
+        assert_eq!(stored_cluster(&pool, MISMATCHED_USER).await, 0);
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    async fn cluster_user_rejects_negative_cluster(pool: PgPool) {
+        mock_external_api();
+        insert_profile(&pool, NEGATIVE_CLUSTER_USER).await;
+
+        let result = cluster_user(
+            State(ProfileDb::new(pool.clone())),
+            Path(NEGATIVE_CLUSTER_USER),
+        )
+        .await;
+
+        assert!(matches!(result, Err(Error::InternalServerError(_))));
+        assert_eq!(stored_cluster(&pool, NEGATIVE_CLUSTER_USER).await, 0);
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    async fn rank_user_stores_ranking(pool: PgPool) {
+        mock_external_api();
+        let user_id = Uuid::new_v4();
+        insert_profile(&pool, user_id).await;
+        let db = ProfileDb::new(pool.clone());
+
+        let Json(ranking) = rank_user(
+            State(db.clone()),
+            Path(user_id),
+            Query(RankQuery { refresh: false }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(ranking.preferences[0].name, "music");
+        assert_eq!(ranking.categories[0].name, "Leisure");
+
+        let stored = db.get_ranking(user_id).await.unwrap().unwrap();
+        assert_eq!(stored.get_typed_preferences()[0].name, "music");
+        assert_eq!(stored.get_typed_categories()[0].name, "Leisure");
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    async fn rank_user_serves_stored_ranking_unless_refreshed(pool: PgPool) {
+        mock_external_api();
+        let user_id = Uuid::new_v4();
+        insert_profile(&pool, user_id).await;
+        let db = ProfileDb::new(pool.clone());
+        let stale = vec![DB::RankedItem {
+            name: "stale".to_string(),
+            score: 0.1,
+        }];
+        db.upsert_ranking(user_id, &stale, &stale).await.unwrap();
+
+        let rank = |refresh| {
+            rank_user(
+                State(db.clone()),
+                Path(user_id),
+                Query(RankQuery { refresh }),
+            )
+        };
+
+        let Json(cached) = rank(false).await.unwrap();
+        assert_eq!(cached.preferences[0].name, "stale");
+
+        let Json(refreshed) = rank(true).await.unwrap();
+        assert_eq!(refreshed.preferences[0].name, "music");
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    async fn rank_user_rejects_mismatched_user(pool: PgPool) {
+        mock_external_api();
+        insert_profile(&pool, MISMATCHED_USER).await;
+        let db = ProfileDb::new(pool.clone());
+
+        let result = rank_user(
+            State(db.clone()),
+            Path(MISMATCHED_USER),
+            Query(RankQuery { refresh: true }),
+        )
+        .await;
+
+        assert!(matches!(result, Err(Error::InternalServerError(_))));
+        assert!(db.get_ranking(MISMATCHED_USER).await.unwrap().is_none());
+    }
+
+    async fn accept(db: &ProfileDb, user_id: Uuid, body: Value) -> Result<Vec<Value>> {
+        let payload: AcceptRecommendationPayload = serde_json::from_value(body).unwrap();
+        let Json(response) =
+            accept_recommendation(State(db.clone()), Path(user_id), Json(payload)).await?;
+
+        Ok(response
+            .days
+            .iter()
+            .map(|day| serde_json::to_value(day).unwrap())
+            .collect())
+    }
+
+    async fn task_names(pool: &PgPool, user_id: Uuid) -> Vec<String> {
+        sqlx::query_scalar("SELECT name FROM tasks WHERE user_id = $1 ORDER BY start_time")
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    async fn accept_merge_skips_recurring_occurrences(pool: PgPool) {
+        let user_id = Uuid::new_v4();
+        insert_profile(&pool, user_id).await;
+        sqlx::query(
+            "INSERT INTO recurring_tasks
+             (user_id, name, category, start_time, duration_minutes, frequency, start_date)
+             VALUES ($1, 'Standup', 'Work', '09:00', 60, 'daily', '2024-11-01')",
+        )
+        .bind(user_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let days = accept(
+            &ProfileDb::new(pool.clone()),
+            user_id,
+            json!({
+                "mode": "merge",
+                "days": [{ "date": "2024-11-25", "day": "Monday", "tasks": [
+                    { "name": "Read", "startTime": "09:30", "endTime": "10:30" },
+                    { "name": "Walk", "startTime": "11:00", "endTime": "12:00" },
+                ]}],
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(days[0]["tasks_skipped"], json!(["Read"]));
+        assert_eq!(task_names(&pool, user_id).await, ["Walk"]);
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    async fn accept_rejects_overlong_name(pool: PgPool) {
+        let user_id = Uuid::new_v4();
+        insert_profile(&pool, user_id).await;
+
+        let result = accept(
+            &ProfileDb::new(pool.clone()),
+            user_id,
+            json!({
+                "days": [{ "date": "2024-11-25", "day": "Monday", "tasks": [
+                    { "name": "Walk", "startTime": "08:00", "endTime": "09:00" },
+                    { "name": "x".repeat(256), "startTime": "11:00", "endTime": "12:00" },
+                ]}],
+            }),
+        )
+        .await;
+
+        assert!(matches!(result, Err(Error::Validation(_))));
+        assert!(task_names(&pool, user_id).await.is_empty());
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    async fn accept_replace_removes_overnight_tasks(pool: PgPool) {
+        let user_id = Uuid::new_v4();
+        insert_profile(&pool, user_id).await;
+        sqlx::query(
+            "INSERT INTO tasks
+             (user_id, schedule_date, end_date, name, category, start_time, end_time)
+             VALUES ($1, '2024-11-24', '2024-11-25', 'Night shift', 'Work',
+                     '2024-11-24 22:00Z', '2024-11-25 02:00Z')",
+        )
+        .bind(user_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let days = accept(
+            &ProfileDb::new(pool.clone()),
+            user_id,
+            json!({
+                "mode": "replace",
+                "days": [{ "date": "2024-11-25", "day": "Monday", "tasks": [
+                    { "name": "Sleep", "startTime": "01:00", "endTime": "07:00" },
+                ]}],
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(days[0]["tasks_removed"], 1);
+        assert_eq!(days[0]["tasks_skipped"], json!([]));
+        assert_eq!(task_names(&pool, user_id).await, ["Sleep"]);
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    async fn accept_replace_keeps_overnight_task_from_previous_day(pool: PgPool) {
+        let user_id = Uuid::new_v4();
+        insert_profile(&pool, user_id).await;
+
+        let days = accept(
+            &ProfileDb::new(pool.clone()),
+            user_id,
+            json!({
+                "mode": "replace",
+                "days": [
+                    { "date": "2024-11-25", "day": "Monday", "tasks": [
+                        { "name": "Night shift", "startTime": "22:00", "endTime": "02:00" },
+                    ]},
+                    { "date": "2024-11-26", "day": "Tuesday", "tasks": [
+                        { "name": "Gym", "startTime": "07:00", "endTime": "08:00" },
+                    ]},
+                ],
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(days[1]["tasks_removed"], 0);
+        assert_eq!(task_names(&pool, user_id).await, ["Night shift", "Gym"]);
+    }
+}
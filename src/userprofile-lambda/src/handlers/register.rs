@@ -1,16 +1,37 @@
-use axum::Json;
+use axum::{extract::State, http::StatusCode, Json};
 
-use crate::{db::ProfileDb, models::RegisterUserPayload};
-use common::error::Result;
+use crate::{
+    db::ProfileDb,
+    models::{RegisterUserPayload, UserProfile},
+};
+use common::{
+    error::{Error, Result},
+    services::{mw_access::ADMIN_SCOPE, mw_auth::AuthUser},
+};
 
-// [TODO]
-// POST /v1/signup
-pub async fn create_user_profile(Json(payload): Json<RegisterUserPayload>) -> Result<()> {
-    let db = ProfileDb::new().await?;
-    db.create_user(payload).await?;
+/// POST: /v1/signup
+///
+/// Registers a new user, storing their profile, scores, preferences and routine
+///
+/// Returns:
+///   - 201: The created user profile
+///   - 400: If the routine times are not in HH:MM format
+///   - 403: If `user_id` is not the caller (unless they hold the admin scope)
+///   - 409: If the user already exists
+///   - 500: For server errors
+pub async fn create_user_profile(
+    State(db): State<ProfileDb>,
+    user: AuthUser,
+    Json(payload): Json<RegisterUserPayload>,
+) -> Result<(StatusCode, Json<UserProfile>)> {
+    if user.user_id() != Some(payload.user_id) && !user.has_scope(ADMIN_SCOPE) {
+        return Err(Error::forbidden(
+            "Cannot register a profile for another user",
+        ));
+    }
 
-    // TODO:
-    //      - Store registeration payload into Database
+    let profile_db = db.create_user(payload).await?;
+    let profile = UserProfile::from(&profile_db);
 
-    Ok(())
+    Ok((StatusCode::CREATED, Json(profile)))
 }
@@ -0,0 +1,60 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use uuid::Uuid;
+
+use crate::{db::ProfileDb, models::RoutineData};
+use common::error::{Error, Result};
+
+/// GET: /v1/user/:user_id/routine
+///
+/// Returns the user's stored routine (work start/end and sleep time, HH:MM)
+///
+/// Returns:
+///   - 200: The user's routine
+///   - 404: If no routine is stored for the user
+///   - 500: For server errors
+pub async fn get_routine(
+    State(db): State<ProfileDb>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<RoutineData>> {
+    let routine_db = db
+        .get_routine(user_id)
+        .await?
+        .ok_or_else(|| Error::not_found(format!("Routine for user {}", user_id)))?;
+
+    Ok(Json(RoutineData::from(&routine_db)))
+}
+
+/// PUT: /v1/user/:user_id/routine
+///
+/// Creates or replaces the user's routine
+///
+/// Request Body:
+/// ```json
+/// {
+///     "work_time_start": "09:00",
+///     "work_time_end": "17:00",
+///     "sleep_time": "23:00"
+/// }
+/// ```
+///
+/// Returns:
+///   - 200: The stored routine
+///   - 400: If any time is not in HH:MM format
+///   - 404: If the user does not exist
+///   - 500: For server errors
+pub async fn put_routine(
+    State(db): State<ProfileDb>,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<RoutineData>,
+) -> Result<Json<RoutineData>> {
+    let (work_start, work_end, sleep) = payload.parse_times()?;
+
+    let routine_db = db
+        .upsert_routine(user_id, work_start, work_end, sleep)
+        .await?;
+
+    Ok(Json(RoutineData::from(&routine_db)))
+}
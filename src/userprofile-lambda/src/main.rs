@@ -1,18 +1,30 @@
-use axum::{
-    middleware,
-    routing::{get, post},
-    Router,
-};
+use axum::{http::Method, middleware};
 use lambda_http::{run, Error};
-use std::env::set_var;
+use std::{
+    env::{self, set_var},
+    sync::Arc,
+};
 
-use common::services::{cors::cors_middleware, mw_auth::auth};
-use handlers::{profile, profiles, recommend, register};
+use common::{
+    database::{create_pool, migrations, DatabaseConfig},
+    routes::{self, ManifestRouter},
+    services::{
+        cors::{cors_middleware, CorsConfig},
+        jwt::JwtVerifier,
+        mw_access::{require_path_user, CLUSTERS_WRITE_SCOPE, PROFILES_ADMIN_SCOPE},
+        mw_auth::auth,
+    },
+};
+use db::ProfileDb;
+use handlers::{export, profile, profiles, recommend, register, routine};
 
 mod db;
 mod handlers;
 mod models;
 
+/// Logical ID of this function in template.yaml
+const SAM_FUNCTION: &str = "UserProfileFunction";
+
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     tracing_subscriber::fmt()
@@ -24,23 +36,112 @@ async fn main() -> Result<(), Error> {
         .json()
         .init();
 
+    let routes = routes();
+    if routes::handle_cli(&routes.manifest(), SAM_FUNCTION)? {
+        return Ok(());
+    }
+
     set_var("AWS_LAMBDA_HTTP_IGNORE_STAGE_IN_PATH", "true");
 
-    let app = Router::new()
-        .route("/v1/signup", post(register::create_user_profile))
-        .route("/v1/user/profile/:id", get(profile::get_profile))
-        .route("/v1/user/profiles", get(profiles::get_profiles))
-        .route("/v1/user/cluster/update", post(recommend::update_cluser))
-        .route("/v1/user/profiles/batch", post(profiles::get_batch))
-        .route("/v1/cluster/:user_id", get(recommend::cluster_user))
-        .route("/v1/rank/:user_id", get(recommend::rank_user))
-        .route("/v1/recommend/:user_id", get(recommend::get_recommendation))
+    // Created once per cold start so warm invocations reuse open connections
+    let pool = create_pool(DatabaseConfig::new()?).await?;
+
+    // Opt-in so that only one function (or the migrate binary) owns schema changes
+    if env::var("RUN_MIGRATIONS").is_ok_and(|v| v == "true") {
+        migrations::run_migrations(&pool).await?;
+    }
+
+    let verifier = Arc::new(JwtVerifier::from_env()?);
+    let cors = Arc::new(CorsConfig::from_env()?);
+
+    let app = routes
+        .into_router()
+        .route_layer(middleware::from_fn(require_path_user))
+        .with_state(ProfileDb::new(pool))
+        .layer(middleware::from_fn_with_state(verifier, auth))
+        .layer(middleware::from_fn_with_state(cors, cors_middleware));
+
+    run(app).await
+}
+
+/// Every route this function serves; template.yaml must declare the same set
+fn routes() -> ManifestRouter<ProfileDb> {
+    ManifestRouter::new()
+        .route(Method::POST, "/v1/signup", register::create_user_profile)
+        .route(
+            Method::GET,
+            "/v1/user/profile/:user_id",
+            profile::get_profile,
+        )
+        .route(
+            Method::PUT,
+            "/v1/user/:user_id/timezone",
+            profile::put_timezone,
+        )
         .route(
+            Method::GET,
+            "/v1/user/:user_id/routine",
+            routine::get_routine,
+        )
+        .route(
+            Method::PUT,
+            "/v1/user/:user_id/routine",
+            routine::put_routine,
+        )
+        .scoped_route(
+            Method::GET,
+            "/v1/user/profiles",
+            profiles::get_profiles,
+            PROFILES_ADMIN_SCOPE,
+        )
+        .scoped_route(
+            Method::POST,
+            "/v1/user/profiles/batch",
+            profiles::get_batch,
+            PROFILES_ADMIN_SCOPE,
+        )
+        .scoped_route(
+            Method::GET,
+            "/v1/export/profiles.ndjson",
+            export::export_profiles,
+            PROFILES_ADMIN_SCOPE,
+        )
+        .scoped_route(
+            Method::POST,
+            "/v1/user/cluster/update",
+            recommend::update_cluser,
+            CLUSTERS_WRITE_SCOPE,
+        )
+        .route(Method::GET, "/v1/cluster/:user_id", recommend::cluster_user)
+        .route(Method::GET, "/v1/rank/:user_id", recommend::rank_user)
+        .route(
+            Method::GET,
+            "/v1/recommend/:user_id",
+            recommend::get_recommendation,
+        )
+        .route(
+            Method::GET,
             "/v1/recommend/:user_id/week",
-            get(recommend::get_recommendation_week),
+            recommend::get_recommendation_week,
+        )
+        .route(
+            Method::POST,
+            "/v1/recommend/:user_id/accept",
+            recommend::accept_recommendation,
         )
-        .layer(middleware::from_fn(cors_middleware))
-        .layer(middleware::from_fn(auth));
+}
 
-    run(app).await
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_match_template() {
+        routes::check_against_template(
+            &routes().manifest(),
+            include_str!("../../../template.yaml"),
+            SAM_FUNCTION,
+        )
+        .unwrap();
+    }
 }
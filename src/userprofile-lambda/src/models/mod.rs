@@ -2,11 +2,18 @@ mod cluster;
 mod profiles;
 mod recommend;
 mod register;
+mod routine;
 
 pub use cluster::UpdateClustersPayload;
-pub use profiles::{convert_profiles, UserProfile, UserProfilesBatchRequest, UserProfilesQuery};
+pub use profiles::{
+    convert_profiles, ExportQuery, ProfileFilter, TimezoneData, UserProfile,
+    UserProfilesBatchRequest, UserProfilesPage, UserProfilesQuery,
+};
 pub use recommend::{
-    RequestClusterUser, RequestRankUser, RequestRecommend, ResponseRecommendDaily,
-    ResponseRecommendWeekly,
+    AcceptMode, AcceptRecommendationPayload, AcceptRecommendationResponse, AcceptedDay,
+    DaySchedule, PlannedTask, RankQuery, RecommendPeriod, RequestClusterUser, RequestRankUser,
+    RequestRecommend, ResponseClusterUser, ResponseRankUser, ResponseRecommendDaily,
+    ResponseRecommendWeekly, UserRanking,
 };
 pub use register::RegisterUserPayload;
+pub use routine::RoutineData;
@@ -1,7 +1,11 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use common::models::database as DB;
+use common::{
+    error::{Error, Result},
+    models::database as DB,
+};
 
 // POST Request structure for /user/profiles/batch
 #[derive(Deserialize)]
@@ -9,10 +13,47 @@ pub struct UserProfilesBatchRequest {
     pub user_ids: Vec<Uuid>,
 }
 
+pub const DEFAULT_PAGE_SIZE: i64 = 100;
+pub const MAX_PAGE_SIZE: i64 = 500;
+
 // GET Query structure for /user/profiles
 #[derive(Deserialize)]
 pub struct UserProfilesQuery {
     pub cluster: Option<i32>,
+    pub limit: Option<i64>,
+    pub after: Option<Uuid>,             // next_cursor of the previous page
+    pub preferences_any: Option<String>, // comma separated
+    pub preferences_all: Option<String>, // comma separated
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub updated_after: Option<DateTime<Utc>>,
+    pub updated_before: Option<DateTime<Utc>>,
+}
+
+// GET Query structure for /export/profiles.ndjson
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    pub since: Option<DateTime<Utc>>, // only profiles updated at or after it
+    pub cursor: Option<String>,       // x-next-cursor of the previous page
+}
+
+// Validated UserProfilesQuery, as used by ProfileDb::get_profiles_page
+pub struct ProfileFilter {
+    pub cluster: Option<i32>,
+    pub preferences_any: Option<Vec<String>>,
+    pub preferences_all: Option<Vec<String>>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub updated_after: Option<DateTime<Utc>>,
+    pub updated_before: Option<DateTime<Utc>>,
+}
+
+// Response structure for GET /user/profiles
+#[derive(Serialize)]
+pub struct UserProfilesPage {
+    pub profiles: Vec<UserProfile>,
+    pub total: i64,                // profiles matching the filters, across all pages
+    pub next_cursor: Option<Uuid>, // None on the last page
 }
 
 #[derive(Serialize, Deserialize)]
@@ -21,6 +62,13 @@ pub struct UserProfile {
     pub cluster: i32,
     pub scores: PersonalityScores,
     pub preferences: Vec<String>,
+    pub timezone: String,
+}
+
+// Request/Response structure for /user/:user_id/timezone
+#[derive(Serialize, Deserialize)]
+pub struct TimezoneData {
+    pub timezone: String,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -37,6 +85,69 @@ pub struct PersonalityScores {
     pub turbulent: f32,
 }
 
+impl UserProfilesQuery {
+    // Page size, defaulting to DEFAULT_PAGE_SIZE
+    pub fn limit(&self) -> Result<i64> {
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+            return Err(Error::validation(format!(
+                "Limit must be between 1 and {}",
+                MAX_PAGE_SIZE
+            )));
+        }
+        Ok(limit)
+    }
+
+    pub fn filter(&self) -> Result<ProfileFilter> {
+        if self.cluster.is_some_and(|cluster| cluster < 0) {
+            return Err(Error::validation(
+                "Cluster ID must be a non-negative integer",
+            ));
+        }
+
+        let window =
+            |after: Option<DateTime<Utc>>, before: Option<DateTime<Utc>>, name: &str| match (
+                after, before,
+            ) {
+                (Some(after), Some(before)) if before <= after => Err(Error::validation(format!(
+                    "{name}_before must be later than {name}_after"
+                ))),
+                _ => Ok(()),
+            };
+        window(self.created_after, self.created_before, "created")?;
+        window(self.updated_after, self.updated_before, "updated")?;
+
+        Ok(ProfileFilter {
+            cluster: self.cluster,
+            preferences_any: split_preferences(self.preferences_any.as_deref())?,
+            preferences_all: split_preferences(self.preferences_all.as_deref())?,
+            created_after: self.created_after,
+            created_before: self.created_before,
+            updated_after: self.updated_after,
+            updated_before: self.updated_before,
+        })
+    }
+}
+
+// "a, b" -> ["a", "b"]; an empty list would match everything (all) or nothing (any)
+fn split_preferences(list: Option<&str>) -> Result<Option<Vec<String>>> {
+    let Some(list) = list else {
+        return Ok(None);
+    };
+
+    let preferences: Vec<String> = list
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(String::from)
+        .collect();
+
+    if preferences.is_empty() {
+        return Err(Error::validation("Preference filters must not be empty"));
+    }
+    Ok(Some(preferences))
+}
+
 // Convert from DB::Profile to UserProfile
 impl From<&DB::Profile> for UserProfile {
     fn from(db_profile: &DB::Profile) -> Self {
@@ -46,6 +157,7 @@ impl From<&DB::Profile> for UserProfile {
             user_id: db_profile.user_id,
             cluster: db_profile.cluster,
             preferences: db_profile.preferences.clone(),
+            timezone: db_profile.timezone.clone(),
             scores: PersonalityScores {
                 introverted: scores.introverted,
                 extraverted: scores.extraverted,
@@ -1,9 +1,13 @@
-use chrono::{NaiveTime, Timelike};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
 
-use common::models::database as DB;
+use common::{
+    error::{Error, Result},
+    models::database as DB,
+};
 
 // Used for calling external_api/cluster
 #[derive(Serialize)]
@@ -13,6 +17,13 @@ pub struct RequestClusterUser {
     preferences: Vec<String>,
 }
 
+// Response from external_api/cluster
+#[derive(Deserialize)]
+pub struct ResponseClusterUser {
+    pub user_id: Uuid,
+    pub cluster: i32,
+}
+
 // Used for calling external_api/rank
 #[derive(Serialize)]
 pub struct RequestRankUser {
@@ -22,6 +33,30 @@ pub struct RequestRankUser {
     cluster: i32,
 }
 
+// Response from external_api/rank
+#[derive(Deserialize)]
+pub struct ResponseRankUser {
+    pub user_id: Uuid,
+    pub preferences: Vec<DB::RankedItem>,
+    pub categories: Vec<DB::RankedItem>,
+}
+
+// GET Query structure for /rank/:user_id
+#[derive(Deserialize)]
+pub struct RankQuery {
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+// Response structure for /rank/:user_id
+#[derive(Serialize)]
+pub struct UserRanking {
+    pub user_id: Uuid,
+    pub preferences: Vec<DB::RankedItem>,
+    pub categories: Vec<DB::RankedItem>,
+    pub ranked_at: String, // ISO 8601
+}
+
 // Used for calling external_api
 #[derive(Serialize)]
 pub struct RequestRecommend {
@@ -36,18 +71,20 @@ pub struct RequestRecommend {
 
 #[derive(Deserialize, Serialize)]
 pub struct ResponseRecommendDaily {
-    date: String,
-    day: String,
-    tasks: Vec<Task>,
+    pub date: String,
+    pub day: String,
+    pub tasks: Vec<Task>,
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct Task {
     #[serde(rename = "endTime")]
-    end_time: String,
-    name: String,
+    pub end_time: String,
+    pub name: String,
     #[serde(rename = "startTime")]
-    start_time: String,
+    pub start_time: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -63,6 +100,134 @@ pub struct DaySchedule {
     pub tasks: Vec<Task>,
 }
 
+// How accepted tasks combine with the ones already on a day
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AcceptMode {
+    // Keep existing tasks, adding only recommended tasks that don't overlap them
+    #[default]
+    Merge,
+    // Remove the day's existing tasks first
+    Replace,
+}
+
+// Which recommendation to fetch when none is sent
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RecommendPeriod {
+    #[default]
+    Day,
+    Week,
+}
+
+// POST Request structure for /recommend/:user_id/accept
+#[derive(Deserialize)]
+pub struct AcceptRecommendationPayload {
+    #[serde(default)]
+    pub mode: AcceptMode,
+    #[serde(default)]
+    pub period: RecommendPeriod,
+    // Days from /recommend/:user_id (a single day) or /recommend/:user_id/week;
+    // re-fetched for `period` when omitted
+    pub days: Option<Vec<DaySchedule>>,
+}
+
+// A recommended task resolved to concrete times, ready to be stored
+pub struct PlannedTask {
+    pub date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub name: String,
+    pub category: String,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+}
+
+// Response structure for /recommend/:user_id/accept
+#[derive(Serialize)]
+pub struct AcceptRecommendationResponse {
+    pub user_id: Uuid,
+    pub mode: AcceptMode,
+    pub days: Vec<AcceptedDay>,
+}
+
+#[derive(Serialize)]
+pub struct AcceptedDay {
+    pub date: String,
+    pub task_ids: Vec<Uuid>,        // Tasks created from the recommendation
+    pub tasks_removed: u64,         // Existing tasks removed by "replace"
+    pub tasks_skipped: Vec<String>, // Recommended tasks left out by "merge" (overlaps)
+}
+
+impl From<ResponseRecommendDaily> for DaySchedule {
+    fn from(daily: ResponseRecommendDaily) -> Self {
+        Self {
+            date: daily.date,
+            day: daily.day,
+            tasks: daily.tasks,
+        }
+    }
+}
+
+impl DaySchedule {
+    /// Resolves the day's tasks to UTC, reading their HH:MM times in `tz`
+    ///
+    /// A task ending at or before its start time runs past midnight
+    pub fn plan(&self, tz: Tz) -> Result<(NaiveDate, Vec<PlannedTask>)> {
+        let date = NaiveDate::parse_from_str(&self.date, "%Y-%m-%d")
+            .map_err(|_| Error::validation(format!("Invalid date: {}", self.date)))?;
+
+        let to_utc = |local: NaiveDateTime| {
+            tz.from_local_datetime(&local)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc))
+                .ok_or_else(|| {
+                    Error::validation(format!(
+                        "{} does not exist in timezone {}",
+                        local.format("%Y-%m-%d %H:%M"),
+                        tz.name()
+                    ))
+                })
+        };
+
+        let tasks = self
+            .tasks
+            .iter()
+            .map(|task| {
+                let start_time = parse_recommended_time(&task.start_time)?;
+                let end_time = parse_recommended_time(&task.end_time)?;
+                let end_date = match end_time <= start_time {
+                    true => date.succ_opt().unwrap_or(date),
+                    false => date,
+                };
+
+                Ok(PlannedTask {
+                    date,
+                    end_date,
+                    name: task.name.clone(),
+                    category: task
+                        .category
+                        .clone()
+                        .unwrap_or_else(|| RECOMMENDED_CATEGORY.to_string()),
+                    start_time: to_utc(date.and_time(start_time))?,
+                    end_time: to_utc(end_date.and_time(end_time))?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((date, tasks))
+    }
+}
+
+// Category given to recommended tasks the external API doesn't categorise
+const RECOMMENDED_CATEGORY: &str = "Recommended";
+
+// The external API sends HH:MM (occasionally with seconds)
+fn parse_recommended_time(time: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M:%S"))
+        .map_err(|_| Error::validation(format!("Invalid recommended time: {}", time)))
+}
+
 impl RequestClusterUser {
     pub fn new(user_id: Uuid, scores: DB::PersonalityScores, preferences: Vec<String>) -> Self {
         Self {
@@ -89,6 +254,18 @@ impl RequestRankUser {
     }
 }
 
+// Convert from DB::Ranking to UserRanking
+impl From<&DB::Ranking> for UserRanking {
+    fn from(ranking: &DB::Ranking) -> Self {
+        Self {
+            user_id: ranking.user_id,
+            preferences: ranking.get_typed_preferences(),
+            categories: ranking.get_typed_categories(),
+            ranked_at: ranking.ranked_at.to_rfc3339(),
+        }
+    }
+}
+
 impl RequestRecommend {
     pub fn new(
         user_id: Uuid,
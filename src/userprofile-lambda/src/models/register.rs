@@ -1,21 +1,25 @@
+use chrono_tz::Tz;
 use serde::Deserialize;
 
-use common::models::database as DB;
+use super::routine::RoutineData;
+use common::{error::Result, models::database as DB};
 use uuid::Uuid;
 
-#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 pub struct RegisterUserPayload {
-    user_id: Uuid,
-    routine: RoutineData,
-    scores: DB::PersonalityScores,
-    preferences: Vec<String>,
+    pub user_id: Uuid,
+    pub routine: RoutineData,
+    pub scores: DB::PersonalityScores,
+    pub preferences: Vec<String>,
+    pub timezone: Option<String>, // IANA name, defaults to UTC
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize, Debug)]
-pub struct RoutineData {
-    work_time_start: String,
-    work_time_end: String,
-    sleep_time: String,
+impl RegisterUserPayload {
+    /// Validates the optional timezone, falling back to UTC when omitted
+    pub fn parse_timezone(&self) -> Result<Tz> {
+        match &self.timezone {
+            Some(name) => DB::parse_timezone(name),
+            None => Ok(Tz::UTC),
+        }
+    }
 }
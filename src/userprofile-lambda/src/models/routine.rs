@@ -0,0 +1,42 @@
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+use common::{
+    error::{Error, Result},
+    models::database as DB,
+};
+
+// Request/Response structure for /user/:user_id/routine (also embedded in /signup)
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RoutineData {
+    pub work_time_start: String,
+    pub work_time_end: String,
+    pub sleep_time: String,
+}
+
+impl RoutineData {
+    /// Parses the HH:MM strings into (work_start, work_end, sleep)
+    pub fn parse_times(&self) -> Result<(NaiveTime, NaiveTime, NaiveTime)> {
+        let parse = |time: &str, field: &str| {
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .map_err(|_| Error::validation(format!("{field} must be in HH:MM format")))
+        };
+
+        Ok((
+            parse(&self.work_time_start, "work_time_start")?,
+            parse(&self.work_time_end, "work_time_end")?,
+            parse(&self.sleep_time, "sleep_time")?,
+        ))
+    }
+}
+
+// Convert from DB::Routine to RoutineData
+impl From<&DB::Routine> for RoutineData {
+    fn from(routine: &DB::Routine) -> Self {
+        Self {
+            work_time_start: routine.work_start_time.format("%H:%M").to_string(),
+            work_time_end: routine.work_end_time.format("%H:%M").to_string(),
+            sleep_time: routine.sleep_time.format("%H:%M").to_string(),
+        }
+    }
+}
@@ -1,33 +1,84 @@
-use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
-use sqlx::{postgres::PgRow, PgPool, Row};
+use async_stream::try_stream;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use futures_util::{Stream, TryStreamExt};
+use rand::Rng;
+use sqlx::{postgres::PgRow, PgPool, Postgres, Row, Transaction};
 use uuid::Uuid;
 
+use crate::models::{
+    ical::ImportedEvent,
+    query::{TaskFilter, DATE_FMT},
+    request::{
+        BulkOperation, CreateRecurringTaskRequest, CreateTaskRequest, EditScope,
+        UpdateOccurrenceRequest, UpdateTaskRequest,
+    },
+};
+
 use common::{
-    database::DatabaseConfig,
+    database::tasks::{self, NewTask},
     error::{Error, Result},
     models::database as DB,
+    ndjson::{self, ExportCursor},
 };
 
+/// Result of `TasksDb::apply_bulk`
+pub enum BulkOutcome {
+    /// Every operation succeeded; the created/updated task per operation (None for deletes)
+    Applied(Vec<Option<DB::Task>>),
+    /// Operation `index` failed and nothing was written
+    Failed { index: usize, error: Error },
+}
+
+/// Outcome of one event of `TasksDb::import_events`
+pub enum ImportOutcome {
+    Created(DB::Task),
+    /// A task was already imported from an event with this UID
+    Duplicate(Uuid),
+    Overlap(Vec<Uuid>),
+}
+
+#[derive(Clone)]
 pub struct TasksDb {
     pool: PgPool,
 }
 
 impl TasksDb {
-    pub async fn new() -> Result<Self> {
-        let config = DatabaseConfig::new()?;
-        let pool = common::database::create_pool(config).await?;
-
-        Ok(Self { pool })
+    /// Wraps a pool created once at startup and shared across invocations
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
     }
 
-    /// Gets all tasks of all users
-    pub async fn get_all_tasks(&self) -> Result<Vec<DB::Task>> {
+    /// Gets a page of tasks across users, matching `filter`
+    ///
+    /// Ordered by (user_id, schedule_date, start_time, id), so pages never skip or repeat
+    /// tasks. Fetches one extra task, which signals there is a next page
+    pub async fn get_tasks_page(&self, filter: &TaskFilter) -> Result<Vec<DB::Task>> {
+        let after = filter.after.as_ref();
         let rows = sqlx::query(
-            "SELECT id, user_id, schedule_date, name, category,
+            "SELECT id, user_id, schedule_date, end_date, name, category,
                     start_time, end_time, completed, created_at, updated_at
              FROM tasks
-             ORDER BY user_id, schedule_date, start_time",
+             WHERE ($1::date IS NULL OR end_date >= $1)
+               AND ($2::date IS NULL OR schedule_date <= $2)
+               AND ($3::text IS NULL OR category = $3)
+               AND ($4::bool IS NULL OR completed = $4)
+               AND ($5::uuid IS NULL OR user_id = $5)
+               AND ($6::uuid IS NULL
+                    OR (user_id, schedule_date, start_time, id) > ($6, $7, $8, $9))
+             ORDER BY user_id, schedule_date, start_time, id
+             LIMIT $10",
         )
+        .bind(filter.from)
+        .bind(filter.until)
+        .bind(&filter.category)
+        .bind(filter.completed)
+        .bind(filter.user_id)
+        .bind(after.map(|c| c.user_id))
+        .bind(after.map(|c| c.schedule_date))
+        .bind(after.map(|c| c.start_time))
+        .bind(after.map(|c| c.id))
+        .bind(filter.limit + 1)
         .fetch_all(&self.pool)
         .await
         .map_err(Error::from)?;
@@ -40,14 +91,50 @@ impl TasksDb {
         Ok(tasks)
     }
 
+    /// Streams tasks (optionally only those updated at or after `since`) for export
+    ///
+    /// Ordered by (updated_at, id) and resumed after `after`, yielding at most `limit`
+    /// rows. Reading starts [`ndjson::SINCE_OVERLAP`] earlier to catch late commits, so
+    /// rows can repeat across exports. Recurring tasks and their exceptions aren't
+    /// included. Rows are fetched as the stream is polled, never all held in memory
+    pub fn export_tasks(
+        &self,
+        since: Option<DateTime<Utc>>,
+        after: Option<&ExportCursor>,
+        limit: i64,
+    ) -> impl Stream<Item = Result<DB::Task>> + Send + 'static {
+        let pool = self.pool.clone();
+        let after = after.map(|cursor| (cursor.updated_at, cursor.id));
+        try_stream! {
+            let mut rows = sqlx::query(
+                "SELECT id, user_id, schedule_date, end_date, name, category,
+                        start_time, end_time, completed, created_at, updated_at
+                 FROM tasks
+                 WHERE ($1::timestamptz IS NULL OR updated_at >= $1)
+                   AND ($2::timestamptz IS NULL OR (updated_at, id) > ($2, $3))
+                 ORDER BY updated_at, id
+                 LIMIT $4",
+            )
+            .bind(ndjson::overlapped_since(since))
+            .bind(after.map(|(updated_at, _)| updated_at))
+            .bind(after.map(|(_, id)| id))
+            .bind(limit)
+            .fetch(&pool);
+
+            while let Some(row) = rows.try_next().await.map_err(Error::from)? {
+                yield Self::map_task_row(row)?;
+            }
+        }
+    }
+
     /// Get all tasks for batch of users
     pub async fn get_users_tasks(&self, user_ids: &[Uuid]) -> Result<Vec<DB::Task>> {
         let rows = sqlx::query(
-            "SELECT id, user_id, schedule_date, name, category,
+            "SELECT id, user_id, schedule_date, end_date, name, category,
                     start_time, end_time, completed, created_at, updated_at
              FROM tasks
              WHERE user_id = ANY($1)
-             ORDER BY user_id, schedule_date, start_time",
+             ORDER BY user_id, schedule_date, start_time, id",
         )
         .bind(user_ids)
         .fetch_all(&self.pool)
@@ -63,6 +150,9 @@ impl TasksDb {
     }
 
     /// Get a user's schedule
+    ///
+    /// Tasks are returned for every day they touch in the range, while schedules
+    /// (and their counters) only exist for the days tasks start on
     pub async fn get_user_schedule(
         &self,
         user_id: Uuid,
@@ -78,235 +168,523 @@ impl TasksDb {
         }
     }
 
+    /// Get the timezone a user's task times are expressed in
+    ///
+    /// Users without a profile fall back to UTC
+    pub async fn get_user_timezone(&self, user_id: Uuid) -> Result<Tz> {
+        let timezone: Option<String> =
+            sqlx::query_scalar("SELECT timezone FROM profiles WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(Error::from)?;
+
+        Ok(timezone
+            .and_then(|name| name.parse().ok())
+            .unwrap_or(Tz::UTC))
+    }
+
     /// Add task to a user
+    ///
+    /// The payload's `start`/`end` are local to `tz`; the task is counted on the start
+    /// day and may end on a later one. The schedule row and its counters are
+    /// maintained by the tasks triggers
+    ///
+    /// Unless `allow_overlap` is set, overlapping an existing task or recurring
+    /// occurrence fails with `Error::Overlap`
     pub async fn add_task(
         &self,
         user_id: Uuid,
-        date: NaiveDate,
-        name: &str,
-        category: &str,
-        start_time: &str,
-        end_time: &str,
+        payload: &CreateTaskRequest,
+        tz: Tz,
     ) -> Result<DB::Task> {
         let mut tx = self.pool.begin().await.map_err(Error::from)?;
+        let task = self.insert_task(&mut tx, user_id, payload, tz).await?;
+        tx.commit().await.map_err(Error::from)?;
 
-        let start_time = Self::format_time(date, start_time)?;
-        let end_time = Self::format_time(date, end_time)?;
+        Ok(task)
+    }
 
-        // Insert or update schedule
-        sqlx::query(
-            "INSERT INTO schedules (user_id, schedule_date, total_tasks, completed_tasks)
-             VALUES ($1, $2, 1, 0)
-             ON CONFLICT (user_id, schedule_date)
-             DO UPDATE SET total_tasks = schedules.total_tasks + 1",
+    /// Update a user's task
+    ///
+    /// Moving a task between dates or toggling completion updates the affected
+    /// schedules' counters through the tasks triggers
+    pub async fn update_task(
+        &self,
+        user_id: Uuid,
+        task_id: Uuid,
+        payload: &UpdateTaskRequest,
+        tz: Tz,
+    ) -> Result<DB::Task> {
+        let mut tx = self.pool.begin().await.map_err(Error::from)?;
+        let task = self
+            .apply_task_update(&mut tx, user_id, task_id, payload, tz)
+            .await?;
+        tx.commit().await.map_err(Error::from)?;
+
+        Ok(task)
+    }
+
+    /// Delete a user's task
+    pub async fn delete_task(&self, user_id: Uuid, task_id: Uuid) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(Error::from)?;
+        Self::remove_task(&mut tx, user_id, task_id).await?;
+        tx.commit().await.map_err(Error::from)?;
+
+        Ok(())
+    }
+
+    /// Apply a batch of create/update/delete operations in a single transaction
+    ///
+    /// The first failing operation rolls back the whole batch; errors reaching the
+    /// database itself are returned as `Err`
+    pub async fn apply_bulk(
+        &self,
+        user_id: Uuid,
+        operations: &[BulkOperation],
+        tz: Tz,
+    ) -> Result<BulkOutcome> {
+        let mut tx = self.pool.begin().await.map_err(Error::from)?;
+
+        let mut tasks = Vec::with_capacity(operations.len());
+        for (index, operation) in operations.iter().enumerate() {
+            let result = match operation {
+                BulkOperation::Create(payload) => self
+                    .insert_task(&mut tx, user_id, payload, tz)
+                    .await
+                    .map(Some),
+                BulkOperation::Update { task_id, changes } => self
+                    .apply_task_update(&mut tx, user_id, *task_id, changes, tz)
+                    .await
+                    .map(Some),
+                BulkOperation::Delete { task_id } => Self::remove_task(&mut tx, user_id, *task_id)
+                    .await
+                    .map(|_| None),
+            };
+
+            match result {
+                Ok(task) => tasks.push(task),
+                Err(error @ Error::Database(_)) => return Err(error),
+                // Dropping the transaction rolls back every earlier operation
+                Err(error) => return Ok(BulkOutcome::Failed { index, error }),
+            }
+        }
+
+        tx.commit().await.map_err(Error::from)?;
+
+        Ok(BulkOutcome::Applied(tasks))
+    }
+
+    /// Imports calendar events as tasks in a single transaction
+    ///
+    /// Events whose UID was imported before are skipped, as are (unless `allow_overlap`
+    /// is set) events overlapping an existing task, including ones imported earlier in
+    /// the same batch
+    pub async fn import_events(
+        &self,
+        user_id: Uuid,
+        events: &[ImportedEvent],
+        allow_overlap: bool,
+        tz: Tz,
+    ) -> Result<Vec<ImportOutcome>> {
+        let mut tx = self.pool.begin().await.map_err(Error::from)?;
+
+        let mut outcomes = Vec::with_capacity(events.len());
+        for event in events {
+            let existing: Option<Uuid> =
+                sqlx::query_scalar("SELECT id FROM tasks WHERE user_id = $1 AND ical_uid = $2")
+                    .bind(user_id)
+                    .bind(&event.uid)
+                    .fetch_optional(&mut *tx)
+                    .await
+                    .map_err(Error::from)?;
+
+            if let Some(task_id) = existing {
+                outcomes.push(ImportOutcome::Duplicate(task_id));
+                continue;
+            }
+
+            if !allow_overlap {
+                match tasks::check_overlaps(&mut tx, user_id, event.start, event.end, None, tz)
+                    .await
+                {
+                    Ok(()) => {}
+                    Err(Error::Overlap(ids)) => {
+                        outcomes.push(ImportOutcome::Overlap(ids));
+                        continue;
+                    }
+                    Err(error) => return Err(error),
+                }
+            }
+
+            let task = NewTask {
+                schedule_date: event.start.with_timezone(&tz).date_naive(),
+                end_date: event.end.with_timezone(&tz).date_naive(),
+                name: &event.name,
+                category: &event.category,
+                start_time: event.start,
+                end_time: event.end,
+                ical_uid: Some(&event.uid),
+            };
+            let task = tasks::insert_task(&mut tx, user_id, &task).await?;
+
+            outcomes.push(ImportOutcome::Created(task));
+        }
+
+        tx.commit().await.map_err(Error::from)?;
+
+        Ok(outcomes)
+    }
+
+    /// Add a recurring task to a user
+    ///
+    /// Stored once; occurrences are expanded when schedules are read
+    pub async fn add_recurring_task(
+        &self,
+        user_id: Uuid,
+        payload: &CreateRecurringTaskRequest,
+    ) -> Result<DB::RecurringTask> {
+        let start_time = Self::parse_time(&payload.start_time)?;
+        let start_date = NaiveDate::parse_from_str(&payload.start_date, DATE_FMT)
+            .map_err(|_| Error::validation("Invalid start date format"))?;
+        let until = payload
+            .until
+            .as_deref()
+            .map(|d| NaiveDate::parse_from_str(d, DATE_FMT))
+            .transpose()
+            .map_err(|_| Error::validation("Invalid until date format"))?;
+        let weekdays = payload.weekdays()?;
+
+        let row = sqlx::query(
+            "INSERT INTO recurring_tasks
+             (user_id, name, category, start_time, duration_minutes,
+              frequency, interval, weekdays, start_date, until, count)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+             RETURNING id, user_id, name, category, start_time, duration_minutes,
+                       frequency, interval, weekdays, start_date, until, count,
+                       created_at, updated_at",
         )
         .bind(user_id)
-        .bind(date)
-        .execute(&mut *tx)
+        .bind(&payload.name)
+        .bind(&payload.category)
+        .bind(start_time)
+        .bind(payload.duration_minutes)
+        .bind(&payload.frequency)
+        .bind(payload.interval.unwrap_or(1))
+        .bind(weekdays)
+        .bind(start_date)
+        .bind(until)
+        .bind(payload.count)
+        .fetch_one(&self.pool)
         .await
-        .map_err(Error::from)?;
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db_err) if db_err.is_foreign_key_violation() => {
+                Error::not_found(format!("User {}", user_id))
+            }
+            e => Error::Database(e),
+        })?;
 
-        // Insert task
-        let task = sqlx::query(
-            "INSERT INTO tasks
-             (user_id, schedule_date, name, category, start_time, end_time)
-             VALUES ($1, $2, $3, $4, $5, $6)
-             RETURNING id, user_id, schedule_date, name, category,
-                       start_time, end_time, completed, created_at, updated_at",
+        tasks::map_recurring_row(row)
+    }
+
+    /// Get all of a user's recurring tasks
+    pub async fn get_recurring_tasks(&self, user_id: Uuid) -> Result<Vec<DB::RecurringTask>> {
+        let rows = sqlx::query(
+            "SELECT id, user_id, name, category, start_time, duration_minutes,
+                    frequency, interval, weekdays, start_date, until, count,
+                    created_at, updated_at
+             FROM recurring_tasks
+             WHERE user_id = $1
+             ORDER BY start_date, start_time",
         )
         .bind(user_id)
-        .bind(date)
-        .bind(name)
-        .bind(category)
-        .bind(start_time)
-        .bind(end_time)
-        .fetch_one(&mut *tx)
+        .fetch_all(&self.pool)
         .await
-        .map_err(Error::from)
-        .map(Self::map_task_row)??;
+        .map_err(Error::from)?;
 
-        tx.commit().await.map_err(Error::from)?;
+        rows.into_iter().map(tasks::map_recurring_row).collect()
+    }
 
-        Ok(task)
+    /// Delete a user's recurring task along with all of its occurrences
+    pub async fn delete_recurring_task(&self, user_id: Uuid, recurring_id: Uuid) -> Result<()> {
+        let deleted = sqlx::query("DELETE FROM recurring_tasks WHERE id = $1 AND user_id = $2")
+            .bind(recurring_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map_err(Error::from)?;
+
+        if deleted.rows_affected() == 0 {
+            return Err(Error::not_found(format!("Recurring task {}", recurring_id)));
+        }
+
+        Ok(())
     }
 
-    /// Update a user's task
-    pub async fn update_task(
+    /// Expands a user's recurring tasks into occurrences starting between `from` and `to`
+    ///
+    /// Occurrences are returned as tasks (ID of the recurring task, times in UTC),
+    /// with per-occurrence edits applied and cancelled ones left out
+    pub async fn get_recurring_occurrences(
         &self,
         user_id: Uuid,
-        task_id: Uuid,
-        name: Option<&str>,
-        category: Option<&str>,
-        start_time: Option<&str>,
-        end_time: Option<&str>,
-        completed: Option<bool>,
-        date: Option<NaiveDate>,
+        from: NaiveDate,
+        to: NaiveDate,
+        tz: Tz,
+    ) -> Result<Vec<DB::Task>> {
+        let mut conn = self.pool.acquire().await.map_err(Error::from)?;
+        tasks::recurring_occurrences(&mut conn, user_id, from, to, tz).await
+    }
+
+    /// Edit one occurrence of a recurring task, or it and every later one
+    ///
+    /// Editing "future" from a later occurrence splits the series in two, so
+    /// earlier occurrences keep their original details
+    pub async fn update_occurrence(
+        &self,
+        user_id: Uuid,
+        recurring_id: Uuid,
+        date: NaiveDate,
+        payload: &UpdateOccurrenceRequest,
+        tz: Tz,
     ) -> Result<DB::Task> {
         let mut tx = self.pool.begin().await.map_err(Error::from)?;
 
-        // Get task current state
-        let current_task = sqlx::query(
-            "SELECT schedule_date, completed, start_time, end_time
-             FROM tasks
-             WHERE id = $1 AND user_id = $2",
-        )
-        .bind(task_id)
-        .bind(user_id)
-        .fetch_optional(&mut *tx)
-        .await
-        .map_err(Error::from)?
-        .ok_or_else(|| Error::not_found(format!("Task {}", task_id)))?;
+        let recurring = Self::lock_occurrence(&mut tx, user_id, recurring_id, date).await?;
+        let start_time = payload.parsed_start_time();
 
-        let current_date: NaiveDate = current_task.get("schedule_date");
-        let current_completed: bool = current_task.get("completed");
+        let (recurring, exception) = match payload.scope {
+            EditScope::This => {
+                let row = sqlx::query(
+                    "INSERT INTO recurring_task_exceptions
+                     (recurring_task_id, occurrence_date, name, category, start_time,
+                      duration_minutes, completed)
+                     VALUES ($1, $2, $3, $4, $5, $6, COALESCE($7, false))
+                     ON CONFLICT (recurring_task_id, occurrence_date) DO UPDATE SET
+                        name = COALESCE($3, recurring_task_exceptions.name),
+                        category = COALESCE($4, recurring_task_exceptions.category),
+                        start_time = COALESCE($5, recurring_task_exceptions.start_time),
+                        duration_minutes = COALESCE($6, recurring_task_exceptions.duration_minutes),
+                        completed = COALESCE($7, recurring_task_exceptions.completed),
+                        updated_at = CURRENT_TIMESTAMP
+                     RETURNING recurring_task_id, occurrence_date, name, category, start_time,
+                               duration_minutes, completed, cancelled, created_at, updated_at",
+                )
+                .bind(recurring.id)
+                .bind(date)
+                .bind(payload.name.as_deref())
+                .bind(payload.category.as_deref())
+                .bind(start_time)
+                .bind(payload.duration_minutes)
+                .bind(payload.completed)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(Error::from)?;
 
-        // Now we handle each Optional data type
+                let exception = tasks::map_exception_row(row)?;
+                if exception.cancelled {
+                    return Err(Error::not_found(format!(
+                        "Occurrence of {} on {}",
+                        recurring_id, date
+                    )));
+                }
 
-        if let Some(new_data) = date {
-            let completed_int: i32 = if current_completed { 1 } else { 0 };
-            // If new date is different than old date, we update new & old schedules
-            if new_data != current_date {
-                // Update current (old) schedule counter
-                sqlx::query(
-                    "UPDATE schedules
-                     SET total_tasks = total_tasks - 1,
-                        completed_tasks = completed_tasks - $3
-                     WHERE user_id = $1 AND schedule_date = $2",
+                (recurring, Some(exception))
+            }
+            EditScope::Future => {
+                let recurring = Self::split_recurring_task(&mut tx, recurring, date).await?;
+
+                // Occurrence-level edits (and completion) on later dates are kept
+                let row = sqlx::query(
+                    "UPDATE recurring_tasks SET
+                        name = COALESCE($1, name),
+                        category = COALESCE($2, category),
+                        start_time = COALESCE($3, start_time),
+                        duration_minutes = COALESCE($4, duration_minutes),
+                        updated_at = CURRENT_TIMESTAMP
+                     WHERE id = $5
+                     RETURNING id, user_id, name, category, start_time, duration_minutes,
+                               frequency, interval, weekdays, start_date, until, count,
+                               created_at, updated_at",
                 )
-                .bind(user_id) // $1
-                .bind(current_date) // $2
-                .bind(completed_int) // $3
-                .execute(&mut *tx)
+                .bind(payload.name.as_deref())
+                .bind(payload.category.as_deref())
+                .bind(start_time)
+                .bind(payload.duration_minutes)
+                .bind(recurring.id)
+                .fetch_one(&mut *tx)
                 .await
                 .map_err(Error::from)?;
+                let recurring = tasks::map_recurring_row(row)?;
 
-                // Updates new schedule
-                sqlx::query(
-                    "INSERT INTO schedules (user_id, schedule_date, total_tasks, completed_tasks)
-                     VALUES ($1, $2, 1, $3)
-                     ON CONFLICT (user_id, schedule_date)
-                     DO UPDATE SET
-                        total_tasks = schedules.total_tasks + 1,
-                        completed_tasks = schedules.completed_tasks + $3",
+                let exception = sqlx::query(
+                    "SELECT recurring_task_id, occurrence_date, name, category, start_time,
+                            duration_minutes, completed, cancelled, created_at, updated_at
+                     FROM recurring_task_exceptions
+                     WHERE recurring_task_id = $1 AND occurrence_date = $2",
                 )
-                .bind(user_id)
-                .bind(new_data)
-                .bind(completed_int)
-                .execute(&mut *tx)
+                .bind(recurring.id)
+                .bind(date)
+                .fetch_optional(&mut *tx)
                 .await
-                .map_err(Error::from)?;
+                .map_err(Error::from)?
+                .map(tasks::map_exception_row)
+                .transpose()?;
+
+                (recurring, exception)
             }
-        }
+        };
+
+        tx.commit().await.map_err(Error::from)?;
 
-        if let Some(new_completed) = completed {
-            if new_completed != current_completed {
-                let schedule_date = date.unwrap_or(current_date); // Use new date if provided
-                let completed_change: i32 = if new_completed { 1 } else { -1 };
+        Ok(tasks::occurrence_task(
+            &recurring,
+            date,
+            exception.as_ref(),
+            tz,
+        ))
+    }
+
+    /// Cancel one occurrence of a recurring task, or end the series from it onwards
+    pub async fn delete_occurrence(
+        &self,
+        user_id: Uuid,
+        recurring_id: Uuid,
+        date: NaiveDate,
+        scope: EditScope,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(Error::from)?;
+
+        let recurring = Self::lock_occurrence(&mut tx, user_id, recurring_id, date).await?;
 
+        match scope {
+            EditScope::This => {
                 sqlx::query(
-                    "UPDATE schedules
-                     SET completed_tasks = completed_tasks + $3
-                     WHERE user_id = $1 AND schedule_date = $2",
+                    "INSERT INTO recurring_task_exceptions
+                     (recurring_task_id, occurrence_date, cancelled)
+                     VALUES ($1, $2, true)
+                     ON CONFLICT (recurring_task_id, occurrence_date) DO UPDATE SET
+                        cancelled = true,
+                        updated_at = CURRENT_TIMESTAMP",
                 )
-                .bind(user_id)
-                .bind(schedule_date)
-                .bind(completed_change)
+                .bind(recurring.id)
+                .bind(date)
                 .execute(&mut *tx)
                 .await
                 .map_err(Error::from)?;
             }
+            EditScope::Future => {
+                // Ending on the first occurrence leaves nothing of the series
+                if date == recurring.start_date {
+                    sqlx::query("DELETE FROM recurring_tasks WHERE id = $1")
+                        .bind(recurring.id)
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(Error::from)?;
+                } else {
+                    Self::end_recurring_task(&mut tx, &recurring, date).await?;
+                    sqlx::query(
+                        "DELETE FROM recurring_task_exceptions
+                         WHERE recurring_task_id = $1 AND occurrence_date >= $2",
+                    )
+                    .bind(recurring.id)
+                    .bind(date)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(Error::from)?;
+                }
+            }
         }
 
-        // If start time is specified, use that
-        let start_time = if let Some(time) = start_time {
-            Some(Self::format_time(date.unwrap_or(current_date), time)?)
-        } else {
-            None
-        };
-
-        // If end time is specified, use that
-        let end_time = if let Some(time) = end_time {
-            Some(Self::format_time(date.unwrap_or(current_date), time)?)
-        } else {
-            None
-        };
+        tx.commit().await.map_err(Error::from)?;
 
-        // Validated that end time is later than start time
-        if let (Some(start), Some(end)) = (start_time.as_ref(), end_time.as_ref()) {
-            if end <= start {
-                return Err(Error::validation("End time must be after start time"));
-            }
-        }
+        Ok(())
+    }
 
-        // Build and execute the update query
-        let updated = sqlx::query_as::<_, DB::Task>(
-            "UPDATE tasks SET
-                name = COALESCE($1, name),
-                category = COALESCE($2, category),
-                start_time = COALESCE($3, start_time),
-                end_time = COALESCE($4, end_time),
-                completed = COALESCE($5, completed),
-                schedule_date = COALESCE($6, schedule_date),
-                updated_at = CURRENT_TIMESTAMP
-             WHERE id = $7 AND user_id = $8
-             RETURNING *",
+    /// Recomputes a user's schedule counters from their tasks, repairing any drift
+    ///
+    /// Returns the number of schedules whose counters changed
+    pub async fn recompute_schedule_counters(
+        &self,
+        user_id: Uuid,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<u64> {
+        // Same recount the task triggers run; it locks each schedule before counting.
+        // Materialized so the days are locked in date order
+        let updated: i64 = sqlx::query_scalar(
+            "WITH days AS MATERIALIZED (
+                 SELECT schedule_date FROM schedules
+                 WHERE user_id = $1 AND schedule_date BETWEEN $2 AND $3
+                 ORDER BY schedule_date
+             )
+             SELECT count(*) FILTER (WHERE refresh_schedule_counters($1, schedule_date))
+             FROM days",
         )
-        .bind(name)
-        .bind(category)
-        .bind(start_time)
-        .bind(end_time)
-        .bind(completed)
-        .bind(date)
-        .bind(task_id)
         .bind(user_id)
-        .fetch_one(&mut *tx)
+        .bind(start_date)
+        .bind(end_date)
+        .fetch_one(&self.pool)
         .await
         .map_err(Error::from)?;
 
-        tx.commit().await.map_err(Error::from)?;
-
-        Ok(updated)
+        Ok(updated as u64)
     }
 
-    /// Delete a user's task
-    pub async fn delete_task(&self, user_id: Uuid, task_id: Uuid) -> Result<()> {
-        let mut tx = self.pool.begin().await.map_err(Error::from)?;
+    /// Creates (or rotates) the secret token for a user's calendar feed
+    ///
+    /// Only a SHA-256 hash is stored, so the token is returned once and can't be
+    /// recovered later; rotating invalidates the previous one
+    pub async fn rotate_feed_token(&self, user_id: Uuid) -> Result<String> {
+        let token = hex_encode(&rand::thread_rng().gen::<[u8; 32]>());
 
-        // Get task info
-        let task_row = sqlx::query(
-            "SELECT schedule_date, completed FROM tasks WHERE id = $1 AND user_id = $2",
+        let stored = sqlx::query(
+            "INSERT INTO calendar_feeds (user_id, token_hash)
+             SELECT id, sha256(convert_to($2, 'UTF8')) FROM users WHERE id = $1
+             ON CONFLICT (user_id) DO UPDATE
+             SET token_hash = EXCLUDED.token_hash, created_at = CURRENT_TIMESTAMP",
         )
-        .bind(task_id)
         .bind(user_id)
-        .fetch_optional(&mut *tx)
+        .bind(&token)
+        .execute(&self.pool)
         .await
-        .map_err(Error::from)?
-        .ok_or_else(|| Error::not_found(format!("Task {}", task_id)))?;
+        .map_err(Error::from)?;
 
-        // Delete task
-        sqlx::query("DELETE FROM tasks WHERE id = $1")
-            .bind(task_id)
-            .execute(&mut *tx)
+        if stored.rows_affected() == 0 {
+            return Err(Error::not_found(format!("User {}", user_id)));
+        }
+
+        Ok(token)
+    }
+
+    /// Revokes a user's calendar feed token
+    pub async fn revoke_feed_token(&self, user_id: Uuid) -> Result<()> {
+        let deleted = sqlx::query("DELETE FROM calendar_feeds WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
             .await
             .map_err(Error::from)?;
 
-        // Update schedule
-        sqlx::query(
-            "UPDATE schedules
-                 SET total_tasks = total_tasks - 1,
-                     completed_tasks = completed_tasks - CASE WHEN $3 THEN 1 ELSE 0 END
-                 WHERE user_id = $1 AND schedule_date = $2",
+        if deleted.rows_affected() == 0 {
+            return Err(Error::not_found("Calendar feed"));
+        }
+
+        Ok(())
+    }
+
+    /// Whether `token` is the user's current calendar feed token
+    pub async fn verify_feed_token(&self, user_id: Uuid, token: &str) -> Result<bool> {
+        sqlx::query_scalar(
+            "SELECT EXISTS (
+                 SELECT 1 FROM calendar_feeds
+                 WHERE user_id = $1 AND token_hash = sha256(convert_to($2, 'UTF8'))
+             )",
         )
         .bind(user_id)
-        .bind(task_row.get::<NaiveDate, _>("schedule_date"))
-        .bind(task_row.get::<bool, _>("completed"))
-        .execute(&mut *tx)
+        .bind(token)
+        .fetch_one(&self.pool)
         .await
-        .map_err(Error::from)?;
-
-        tx.commit().await.map_err(Error::from)?;
-
-        Ok(())
+        .map_err(Error::from)
     }
 
     /// Helper function to get a schedule for a single day
@@ -346,12 +724,12 @@ impl TasksDb {
             },
         };
 
-        // Get tasks
+        // Get tasks, including overnight ones started on an earlier day
         let task_rows = sqlx::query(
-            "SELECT id, user_id, schedule_date, name, category,
+            "SELECT id, user_id, schedule_date, end_date, name, category,
                     start_time, end_time, completed, created_at, updated_at
              FROM tasks
-             WHERE user_id = $1 AND schedule_date = $2
+             WHERE user_id = $1 AND schedule_date <= $2 AND end_date >= $2
              ORDER BY start_time",
         )
         .bind(user_id)
@@ -401,12 +779,12 @@ impl TasksDb {
             })
             .collect();
 
-        // Get tasks
+        // Get tasks, including overnight ones started on an earlier day
         let task_rows = sqlx::query(
-            "SELECT id, user_id, schedule_date, name, category,
+            "SELECT id, user_id, schedule_date, end_date, name, category,
                     start_time, end_time, completed, created_at, updated_at
              FROM tasks
-             WHERE user_id = $1 AND schedule_date BETWEEN $2 AND $3
+             WHERE user_id = $1 AND schedule_date <= $3 AND end_date >= $2
              ORDER BY schedule_date, start_time",
         )
         .bind(user_id)
@@ -424,17 +802,308 @@ impl TasksDb {
         Ok((schedules, tasks))
     }
 
-    /// Helper function to convert Date -> Time (HH:MM 24-hr UTC)
-    fn format_time(date: NaiveDate, time: &str) -> Result<DateTime<Utc>> {
-        let naive_time = NaiveTime::parse_from_str(time, "%H:%M")
-            .map_err(|_| Error::validation("Time must be in HH:MM format"))?;
+    /// Helper function to parse HH:MM (24-hr)
+    fn parse_time(time: &str) -> Result<NaiveTime> {
+        NaiveTime::parse_from_str(time, "%H:%M")
+            .map_err(|_| Error::validation("Time must be in HH:MM format"))
+    }
 
-        let naive_datetime = date.and_time(naive_time);
+    /// Helper function to convert a local date and time in the user's timezone -> UTC
+    ///
+    /// Ambiguous times (DST fall-back) resolve to the earlier instant; times
+    /// skipped by a DST spring-forward are rejected
+    fn to_utc(local: NaiveDateTime, tz: Tz) -> Result<DateTime<Utc>> {
+        let local = tz.from_local_datetime(&local).earliest().ok_or_else(|| {
+            Error::validation(format!(
+                "{} does not exist in timezone {}",
+                local.format("%Y-%m-%d %H:%M"),
+                tz.name()
+            ))
+        })?;
 
-        Ok(DateTime::<Utc>::from_naive_utc_and_offset(
-            naive_datetime,
-            Utc,
-        ))
+        Ok(local.with_timezone(&Utc))
+    }
+
+    /// Helper function to insert a task within `tx`
+    async fn insert_task(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: Uuid,
+        payload: &CreateTaskRequest,
+        tz: Tz,
+    ) -> Result<DB::Task> {
+        // Resolve local start/end, which may fall on different days
+        let (start, end) = payload.bounds()?;
+
+        let task = NewTask {
+            schedule_date: start.date(),
+            end_date: end.date(),
+            name: &payload.name,
+            category: &payload.category,
+            start_time: Self::to_utc(start, tz)?,
+            end_time: Self::to_utc(end, tz)?,
+            ical_uid: None,
+        };
+
+        // Local times can still collapse across a DST change
+        task.validate()?;
+
+        if !payload.allow_overlap {
+            tasks::check_overlaps(tx, user_id, task.start_time, task.end_time, None, tz).await?;
+        }
+
+        tasks::insert_task(tx, user_id, &task).await
+    }
+
+    /// Helper function to apply a task update within `tx`
+    async fn apply_task_update(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: Uuid,
+        task_id: Uuid,
+        payload: &UpdateTaskRequest,
+        tz: Tz,
+    ) -> Result<DB::Task> {
+        let parse_date = |date_str: &str| {
+            NaiveDate::parse_from_str(date_str, DATE_FMT).expect("Date format already validated")
+        };
+        let date = payload.date.as_deref().map(parse_date);
+        let end_date = payload.end_date.as_deref().map(parse_date);
+        let start_time = payload.start_time.as_deref();
+        let end_time = payload.end_time.as_deref();
+
+        // Get task current state, locking the row until commit
+        let current_task = sqlx::query(
+            "SELECT schedule_date, end_date, start_time, end_time
+             FROM tasks
+             WHERE id = $1 AND user_id = $2
+             FOR UPDATE",
+        )
+        .bind(task_id)
+        .bind(user_id)
+        .fetch_optional(&mut **tx)
+        .await
+        .map_err(Error::from)?
+        .ok_or_else(|| Error::not_found(format!("Task {}", task_id)))?;
+
+        let current_date: NaiveDate = current_task.get("schedule_date");
+        let current_end_date: NaiveDate = current_task.get("end_date");
+        let current_start: DateTime<Utc> = current_task.get("start_time");
+        let current_end: DateTime<Utc> = current_task.get("end_time");
+
+        // Any change to when the task happens re-resolves both ends in the user's
+        // timezone, so the pair stays consistent (and validated) across dates
+        let reschedule =
+            start_time.is_some() || end_time.is_some() || date.is_some() || end_date.is_some();
+
+        let (date, end_date, start_time, end_time) = if reschedule {
+            let new_date = date.unwrap_or(current_date);
+
+            // Moving the start day moves the end day with it unless given explicitly
+            let new_end_date = match end_date {
+                Some(end_date) => end_date,
+                None => current_end_date
+                    .checked_add_signed(new_date - current_date)
+                    .ok_or_else(|| {
+                        Error::validation("Task ends outside the supported date range")
+                    })?,
+            };
+            if new_end_date < new_date {
+                return Err(Error::validation("End date cannot be before date"));
+            }
+
+            let start_time = match start_time {
+                Some(time) => Self::parse_time(time)?,
+                None => current_start.with_timezone(&tz).time(),
+            };
+            let end_time = match end_time {
+                Some(time) => Self::parse_time(time)?,
+                None => current_end.with_timezone(&tz).time(),
+            };
+
+            let start = Self::to_utc(new_date.and_time(start_time), tz)?;
+            let end = Self::to_utc(new_end_date.and_time(end_time), tz)?;
+
+            // Validated that end time is later than start time
+            if end <= start {
+                return Err(Error::validation("End time must be after start time"));
+            }
+
+            if !payload.allow_overlap {
+                tasks::check_overlaps(tx, user_id, start, end, Some(task_id), tz).await?;
+            }
+
+            (Some(new_date), Some(new_end_date), Some(start), Some(end))
+        } else {
+            (None, None, None, None)
+        };
+
+        // Build and execute the update query
+        let updated = sqlx::query_as::<_, DB::Task>(
+            "UPDATE tasks SET
+                name = COALESCE($1, name),
+                category = COALESCE($2, category),
+                start_time = COALESCE($3, start_time),
+                end_time = COALESCE($4, end_time),
+                completed = COALESCE($5, completed),
+                schedule_date = COALESCE($6, schedule_date),
+                end_date = COALESCE($7, end_date),
+                updated_at = CURRENT_TIMESTAMP
+             WHERE id = $8 AND user_id = $9
+             RETURNING *",
+        )
+        .bind(payload.name.as_deref())
+        .bind(payload.category.as_deref())
+        .bind(start_time)
+        .bind(end_time)
+        .bind(payload.completed)
+        .bind(date)
+        .bind(end_date)
+        .bind(task_id)
+        .bind(user_id)
+        .fetch_one(&mut **tx)
+        .await
+        .map_err(Error::from)?;
+
+        Ok(updated)
+    }
+
+    /// Helper function to delete a task within `tx`
+    async fn remove_task(
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: Uuid,
+        task_id: Uuid,
+    ) -> Result<()> {
+        let deleted = sqlx::query("DELETE FROM tasks WHERE id = $1 AND user_id = $2")
+            .bind(task_id)
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await
+            .map_err(Error::from)?;
+
+        if deleted.rows_affected() == 0 {
+            return Err(Error::not_found(format!("Task {}", task_id)));
+        }
+
+        Ok(())
+    }
+
+    /// Helper function to lock a user's recurring task, ensuring it occurs on `date`
+    async fn lock_occurrence(
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: Uuid,
+        recurring_id: Uuid,
+        date: NaiveDate,
+    ) -> Result<DB::RecurringTask> {
+        let recurring = sqlx::query(
+            "SELECT id, user_id, name, category, start_time, duration_minutes,
+                    frequency, interval, weekdays, start_date, until, count,
+                    created_at, updated_at
+             FROM recurring_tasks
+             WHERE id = $1 AND user_id = $2
+             FOR UPDATE",
+        )
+        .bind(recurring_id)
+        .bind(user_id)
+        .fetch_optional(&mut **tx)
+        .await
+        .map_err(Error::from)?
+        .ok_or_else(|| Error::not_found(format!("Recurring task {}", recurring_id)))
+        .and_then(tasks::map_recurring_row)?;
+
+        if !recurring.occurs_on(date) {
+            return Err(Error::not_found(format!(
+                "Occurrence of {} on {}",
+                recurring_id, date
+            )));
+        }
+
+        Ok(recurring)
+    }
+
+    /// Helper function to split a recurring task at `date`
+    ///
+    /// Returns the series that occurrences from `date` onwards now belong to
+    async fn split_recurring_task(
+        tx: &mut Transaction<'_, Postgres>,
+        recurring: DB::RecurringTask,
+        date: NaiveDate,
+    ) -> Result<DB::RecurringTask> {
+        if date == recurring.start_date {
+            return Ok(recurring);
+        }
+
+        let before = Self::end_recurring_task(tx, &recurring, date).await?;
+
+        // The new series continues the old one: same rule, remaining count
+        let row = sqlx::query(
+            "INSERT INTO recurring_tasks
+             (user_id, name, category, start_time, duration_minutes,
+              frequency, interval, weekdays, start_date, until, count)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+             RETURNING id, user_id, name, category, start_time, duration_minutes,
+                       frequency, interval, weekdays, start_date, until, count,
+                       created_at, updated_at",
+        )
+        .bind(recurring.user_id)
+        .bind(&recurring.name)
+        .bind(&recurring.category)
+        .bind(recurring.start_time)
+        .bind(recurring.duration_minutes)
+        .bind(&recurring.frequency)
+        .bind(recurring.interval)
+        .bind(&recurring.weekdays)
+        .bind(date)
+        .bind(recurring.until)
+        .bind(recurring.count.map(|count| count - before))
+        .fetch_one(&mut **tx)
+        .await
+        .map_err(Error::from)?;
+        let split = tasks::map_recurring_row(row)?;
+
+        sqlx::query(
+            "UPDATE recurring_task_exceptions
+             SET recurring_task_id = $1
+             WHERE recurring_task_id = $2 AND occurrence_date >= $3",
+        )
+        .bind(split.id)
+        .bind(recurring.id)
+        .bind(date)
+        .execute(&mut **tx)
+        .await
+        .map_err(Error::from)?;
+
+        Ok(split)
+    }
+
+    /// Helper function to end a recurring task the day before `date`
+    ///
+    /// Returns how many occurrences the series keeps
+    async fn end_recurring_task(
+        tx: &mut Transaction<'_, Postgres>,
+        recurring: &DB::RecurringTask,
+        date: NaiveDate,
+    ) -> Result<i32> {
+        let until = date
+            .pred_opt()
+            .ok_or_else(|| Error::validation("Invalid date calculation"))?;
+        let kept = recurring.occurrences(recurring.start_date, until).len() as i32;
+
+        sqlx::query(
+            "UPDATE recurring_tasks
+             SET until = $1,
+                 count = CASE WHEN count IS NULL THEN NULL ELSE $2 END,
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE id = $3",
+        )
+        .bind(until)
+        .bind(kept)
+        .bind(recurring.id)
+        .execute(&mut **tx)
+        .await
+        .map_err(Error::from)?;
+
+        Ok(kept)
     }
 
     fn map_task_row(row: PgRow) -> Result<DB::Task> {
@@ -442,6 +1111,7 @@ impl TasksDb {
             id: row.get("id"),
             user_id: row.get("user_id"),
             schedule_date: row.get("schedule_date"),
+            end_date: row.get("end_date"),
             name: row.get("name"),
             category: row.get("category"),
             start_time: row.get("start_time"),
@@ -452,3 +1122,8 @@ impl TasksDb {
         })
     }
 }
+
+// Lowercase hex, as used for feed tokens
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,174 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+
+use crate::db::{ImportOutcome, TasksDb};
+use crate::handlers::schedule::load_schedule;
+use crate::models::{
+    ical::{self, ParsedEvent},
+    query::{DateRangeQuery, FeedQuery, ImportQuery},
+    response::{CalendarTokenResponse, ImportEventResult, ImportResponse, Task},
+};
+use common::error::{Error, Result};
+
+const CALENDAR_NAME: &str = "BustleIt Schedule";
+
+/// Days a feed covers when neither `until` nor `range` is given
+const DEFAULT_FEED_DAYS: u64 = 31;
+
+// POST /v1/user/:user_id/calendar-token - Create or rotate the calendar feed token
+pub async fn rotate_calendar_token(
+    State(db): State<TasksDb>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<CalendarTokenResponse>> {
+    let token = db.rotate_feed_token(user_id).await?;
+
+    Ok(Json(CalendarTokenResponse {
+        feed_path: format!("/v1/user/{}/schedule.ics?token={}", user_id, token),
+        token,
+    }))
+}
+
+// DELETE /v1/user/:user_id/calendar-token - Revoke the calendar feed token
+pub async fn revoke_calendar_token(
+    State(db): State<TasksDb>,
+    Path(user_id): Path<Uuid>,
+) -> Result<()> {
+    db.revoke_feed_token(user_id).await?;
+    Ok(())
+}
+
+/// Exports a user's schedule as an iCalendar (.ics) feed
+///
+/// # Endpoint
+/// ```text
+/// GET /v1/user/:user_id/schedule.ics?token=...
+/// ```
+///
+/// # Query Parameters
+/// - `token`: The user's calendar feed token (see `POST /v1/user/:user_id/calendar-token`)
+/// - `date`, `until`, `range`: Same as `GET /v1/user/:user_id/schedule`, except that the
+///   feed covers 31 days from `date` when neither `until` nor `range` is given
+///
+/// Not behind JWT auth, as calendar apps can only subscribe to a URL. A missing or
+/// invalid token is reported as not found, so feeds can't be probed for
+pub async fn get_calendar_feed(
+    State(db): State<TasksDb>,
+    Path(user_id): Path<Uuid>,
+    Query(feed): Query<FeedQuery>,
+    Query(query): Query<DateRangeQuery>,
+) -> Result<impl IntoResponse> {
+    let token = feed.token.unwrap_or_default();
+    if token.is_empty() || !db.verify_feed_token(user_id, &token).await? {
+        return Err(Error::not_found("Calendar feed"));
+    }
+
+    query.validate_all()?;
+
+    let tz = db.get_user_timezone(user_id).await?;
+    let (start_date, end_date) = query.date_bounds(tz)?;
+    let end_date = match end_date {
+        Some(end_date) => end_date,
+        None => start_date
+            .checked_add_days(chrono::Days::new(DEFAULT_FEED_DAYS - 1))
+            .ok_or_else(|| Error::validation("Invalid date range calculation"))?,
+    };
+
+    let (_, mut entries) = load_schedule(&db, user_id, start_date, Some(end_date), tz).await?;
+    entries.sort_by_key(|(task, _)| task.start_time);
+
+    Ok((
+        [(header::CONTENT_TYPE, ical::CONTENT_TYPE)],
+        ical::render_calendar(CALENDAR_NAME, &entries),
+    ))
+}
+
+/// Imports the events of an iCalendar (.ics) file as tasks
+///
+/// # Endpoint
+/// ```text
+/// POST /v1/user/:user_id/import/ics
+/// ```
+///
+/// # Request Body
+/// The raw .ics file (at most 1000 events)
+///
+/// # Query Parameters
+/// - `allow_overlap`: Optional bool, imports events even if they overlap existing tasks
+///
+/// Events are deduplicated on their UID, so a calendar can be re-imported to pick up new
+/// events. All-day, cancelled and recurring events are skipped
+pub async fn import_ics(
+    State(db): State<TasksDb>,
+    Path(user_id): Path<Uuid>,
+    Query(query): Query<ImportQuery>,
+    body: String,
+) -> Result<Json<ImportResponse>> {
+    let tz = db.get_user_timezone(user_id).await?;
+    let parsed = ical::parse_events(&body, tz)?;
+
+    let events: Vec<_> = parsed
+        .iter()
+        .filter_map(|event| match event {
+            ParsedEvent::Event(event) => Some(event.clone()),
+            _ => None,
+        })
+        .collect();
+    let mut outcomes = db
+        .import_events(user_id, &events, query.allow_overlap, tz)
+        .await?
+        .into_iter();
+
+    // Outcomes line up with the importable events, in file order
+    let results: Vec<_> = parsed
+        .into_iter()
+        .enumerate()
+        .map(|(index, event)| {
+            let (uid, status, reason, task) = match event {
+                ParsedEvent::Event(event) => match outcomes.next() {
+                    Some(ImportOutcome::Created(task)) => (
+                        Some(event.uid),
+                        "created",
+                        None,
+                        Some(Task::in_timezone(task, tz)),
+                    ),
+                    Some(ImportOutcome::Duplicate(task_id)) => (
+                        Some(event.uid),
+                        "skipped",
+                        Some(format!("Already imported as task {}", task_id)),
+                        None,
+                    ),
+                    Some(ImportOutcome::Overlap(ids)) => (
+                        Some(event.uid),
+                        "skipped",
+                        Some(Error::Overlap(ids).to_string()),
+                        None,
+                    ),
+                    None => unreachable!("one import outcome per event"),
+                },
+                ParsedEvent::Skipped { uid, reason } => (uid, "skipped", Some(reason), None),
+                ParsedEvent::Invalid { uid, reason } => (uid, "invalid", Some(reason), None),
+            };
+
+            ImportEventResult {
+                index,
+                uid,
+                status: status.to_string(),
+                reason,
+                task,
+            }
+        })
+        .collect();
+
+    let count = |status: &str| results.iter().filter(|r| r.status == status).count();
+    Ok(Json(ImportResponse {
+        created: count("created"),
+        skipped: count("skipped"),
+        invalid: count("invalid"),
+        events: results,
+    }))
+}
@@ -0,0 +1,54 @@
+use axum::{
+    extract::{Query, State},
+    response::Response,
+};
+
+use crate::db::TasksDb;
+use crate::models::query::ExportQuery;
+use common::{
+    error::Result,
+    ndjson::{self, ExportCursor},
+};
+
+/// Exports tasks as newline-delimited JSON, one stored task per line
+///
+/// # Endpoint
+/// ```text
+/// GET /v1/export/tasks.ndjson
+/// ```
+///
+/// # Query Parameters
+/// - `since`: Optional RFC 3339 timestamp, only exports tasks updated at or after it
+/// - `cursor`: Optional `x-next-cursor` header of the previous page
+///
+/// Responses hold at most [`ndjson::MAX_EXPORT_ROWS`] tasks, to stay under the Lambda
+/// response size limit. While more remain, the `x-next-cursor` header is set; request it
+/// with the same `since` until a response comes without one.
+///
+/// Lines are ordered by `updated_at`, so the last line's `updated_at` can be passed as
+/// the next `since`. Incremental exports re-read the 15 minutes before `since`, as a
+/// task can commit after later-stamped ones were exported, so tasks may repeat and
+/// should be upserted by `id`. Deleted tasks don't appear in incremental exports.
+///
+/// Only one-off tasks are exported: recurring tasks and their per-occurrence edits are
+/// excluded, and can be read per user from `GET /v1/user/:user_id/recurring-tasks` and
+/// the schedule.
+pub async fn export_tasks(
+    State(db): State<TasksDb>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response> {
+    let after = query
+        .cursor
+        .as_deref()
+        .map(ExportCursor::decode)
+        .transpose()?;
+
+    // One extra row tells the page whether another follows
+    let limit = ndjson::MAX_EXPORT_ROWS;
+    let rows = db.export_tasks(query.since, after.as_ref(), limit + 1);
+    ndjson::page(rows, limit, |task| ExportCursor {
+        updated_at: task.updated_at,
+        id: task.id,
+    })
+    .await
+}
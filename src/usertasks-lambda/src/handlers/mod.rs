@@ -1,2 +1,5 @@
+pub mod calendar;
+pub mod export;
+pub mod recurring;
 pub mod schedule;
 pub mod tasks;
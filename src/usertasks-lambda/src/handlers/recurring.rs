@@ -0,0 +1,136 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+use crate::db::TasksDb;
+use crate::models::{
+    query::DATE_FMT,
+    request::{CreateRecurringTaskRequest, EditScopeQuery, UpdateOccurrenceRequest},
+    response::{RecurringTask, Task},
+};
+use common::error::{Error, Result};
+
+// POST /v1/user/:user_id/recurring-tasks - Create recurring task
+pub async fn create_recurring_task(
+    State(db): State<TasksDb>,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<CreateRecurringTaskRequest>,
+) -> Result<Json<RecurringTask>> {
+    // Validates payload's data structure
+    payload.validate_all()?;
+
+    let recurring = db.add_recurring_task(user_id, &payload).await?;
+
+    Ok(Json(RecurringTask::from(recurring)))
+}
+
+// GET /v1/user/:user_id/recurring-tasks - List recurring tasks
+pub async fn get_recurring_tasks(
+    State(db): State<TasksDb>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<Vec<RecurringTask>>> {
+    let recurring = db.get_recurring_tasks(user_id).await?;
+
+    Ok(Json(
+        recurring.into_iter().map(RecurringTask::from).collect(),
+    ))
+}
+
+// DELETE /v1/user/:user_id/recurring-tasks/:recurring_task_id - Delete whole series
+pub async fn delete_recurring_task(
+    State(db): State<TasksDb>,
+    Path((user_id, recurring_id)): Path<(Uuid, Uuid)>,
+) -> Result<()> {
+    db.delete_recurring_task(user_id, recurring_id).await?;
+    Ok(())
+}
+
+/// Edits a single occurrence, or it and all future ones
+///
+/// # Endpoint
+/// ```text
+/// PATCH /v1/user/:user_id/recurring-tasks/:recurring_task_id/occurrences/:date
+/// ```
+///
+/// # Request Body
+/// - `scope`: `"this"` (default) or `"future"`
+/// - `name`, `category`, `start_time` (HH:MM), `duration_minutes`: Optional changes
+/// - `completed`: Optional, only with scope `"this"`
+pub async fn update_occurrence(
+    State(db): State<TasksDb>,
+    Path((user_id, recurring_id, date)): Path<(Uuid, Uuid, String)>,
+    Json(payload): Json<UpdateOccurrenceRequest>,
+) -> Result<Json<Task>> {
+    if payload.is_empty() {
+        return Err(Error::validation("No updates provided"));
+    }
+
+    payload.validate_all()?;
+    let date = parse_occurrence_date(&date)?;
+
+    let tz = db.get_user_timezone(user_id).await?;
+    let task = db
+        .update_occurrence(user_id, recurring_id, date, &payload, tz)
+        .await?;
+
+    Ok(Json(Task::occurrence(task, tz)))
+}
+
+/// Cancels a single occurrence, or ends the series from it onwards
+///
+/// # Endpoint
+/// ```text
+/// DELETE /v1/user/:user_id/recurring-tasks/:recurring_task_id/occurrences/:date
+/// ```
+///
+/// # Query Parameters
+/// - `scope`: `this` (default) or `future`
+pub async fn delete_occurrence(
+    State(db): State<TasksDb>,
+    Path((user_id, recurring_id, date)): Path<(Uuid, Uuid, String)>,
+    Query(query): Query<EditScopeQuery>,
+) -> Result<()> {
+    let date = parse_occurrence_date(&date)?;
+
+    db.delete_occurrence(user_id, recurring_id, date, query.scope)
+        .await?;
+    Ok(())
+}
+
+fn parse_occurrence_date(date: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(date, DATE_FMT)
+        .map_err(|_| Error::validation("Invalid date format. Expected YYYY-MM-DD"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::PgPool;
+
+    use common::database::migrations::MIGRATOR;
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    async fn add_recurring_task_reports_unknown_user(pool: PgPool) {
+        let payload = CreateRecurringTaskRequest {
+            name: "Gym".to_string(),
+            category: "Exercise".to_string(),
+            start_time: "07:00".to_string(),
+            duration_minutes: 60,
+            start_date: "2024-11-25".to_string(),
+            frequency: "daily".to_string(),
+            interval: None,
+            by_weekday: None,
+            until: None,
+            count: None,
+        };
+
+        let result = TasksDb::new(pool)
+            .add_recurring_task(Uuid::new_v4(), &payload)
+            .await;
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+    }
+}
@@ -1,13 +1,17 @@
-use axum::{extract::Path, extract::Query, Json};
-use chrono::NaiveDate;
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{NaiveDate, NaiveTime};
+use chrono_tz::Tz;
 use std::collections::BTreeMap;
 use uuid::Uuid;
 
-use common::error::{Error, Result};
+use common::{error::Result, models::database as DB};
 
 use crate::db::TasksDb;
-use crate::models::query::{DateRangeQuery, DATE_FMT};
-use crate::models::response::{DayTasks, ScheduleResponse, Task};
+use crate::models::query::{occurrence_end, DateRangeQuery, DATE_FMT};
+use crate::models::response::{DayTasks, RecomputeResponse, ScheduleResponse, Task};
 
 /// Retrieves a user's schedule for a specified time period
 ///
@@ -17,59 +21,63 @@ use crate::models::response::{DayTasks, ScheduleResponse, Task};
 /// ```
 ///
 /// # Query Parameters
-/// - `date`: Optional starting date in YYYY-MM-DD format. Defaults to today if not provided
+/// - `date`: Optional starting date in YYYY-MM-DD format. Defaults to today (in the user's timezone) if not provided
 /// - `until`: Optional end date in YYYY-MM-DD format
 /// - `range`: Optional number of days to fetch (1-31). Cannot be used with 'until'
 /// - `skip_empty`: Optional bool, if true, does not return any empty schedules
+///
+/// Tasks that run past midnight are listed on each day they touch, but only count
+/// towards the day they start on. Recurring tasks are expanded into occurrences
+/// (`recurring: true`), which count towards their day like stored tasks. Occurrences
+/// are only expanded for the first 31 days of the range
 pub async fn get_user_schedule(
+    State(db): State<TasksDb>,
     Path(user_id): Path<Uuid>,
     Query(query): Query<DateRangeQuery>,
 ) -> Result<Json<Option<ScheduleResponse>>> {
     query.validate_all()?;
 
-    let db = TasksDb::new().await?;
-
-    // Get start date (or today if not provided)
-    let start_date = query
-        .date
-        .as_ref()
-        .map(|d| NaiveDate::parse_from_str(d, DATE_FMT))
-        .transpose()
-        .map_err(|_| Error::validation("Invalid start date format"))?
-        .unwrap_or_else(|| chrono::Local::now().date_naive());
-
-    // Calculate end date if range or until is provided
-    let end_date = if let Some(until) = &query.until {
-        Some(
-            NaiveDate::parse_from_str(until, DATE_FMT)
-                .map_err(|_| Error::validation("Invalid end date format"))?,
-        )
-    } else if let Some(range) = query.range {
-        Some(
-            start_date
-                .checked_add_days(chrono::Days::new((range - 1) as u64))
-                .ok_or_else(|| Error::validation("Invalid date range calculation"))?,
-        )
-    } else {
-        None
-    };
+    // Dates and times are interpreted in the user's own timezone
+    let tz = db.get_user_timezone(user_id).await?;
 
-    // Fetch schedule and tasks from database
-    let (schedules, tasks) = db.get_user_schedule(user_id, start_date, end_date).await?;
+    // Get start date (or today if not provided) and end date if range or until is provided
+    let (start_date, end_date) = query.date_bounds(tz)?;
 
-    if schedules.is_empty() && query.skip_empty {
+    let last_date = end_date.unwrap_or(start_date);
+    let (schedules, entries) = load_schedule(&db, user_id, start_date, end_date, tz).await?;
+
+    if schedules.is_empty() && entries.is_empty() && query.skip_empty {
         return Ok(Json(None));
     }
 
     let mut response = ScheduleResponse::new(user_id.to_string());
 
-    // Group tasks by date
+    // Occurrences aren't stored as tasks, so they count towards their start day here
+    let mut occurrence_counts = BTreeMap::new();
+    for (occurrence, _) in entries.iter().filter(|(_, recurring)| *recurring) {
+        if occurrence.schedule_date >= start_date {
+            let (total, completed) = occurrence_counts
+                .entry(occurrence.schedule_date)
+                .or_insert((0, 0));
+            *total += 1;
+            *completed += occurrence.completed as i32;
+        }
+    }
+
+    // Group tasks by every day they touch within the requested range
     let mut task_groups = BTreeMap::new();
-    for task in tasks {
-        task_groups
-            .entry(task.schedule_date)
-            .or_insert_with(Vec::new)
-            .push(task);
+    for (task, recurring) in entries {
+        let (first, last) = task_days(&task, tz);
+        let mut current = first.max(start_date);
+        while current <= last.min(last_date) {
+            task_groups
+                .entry(current)
+                .or_insert_with(Vec::new)
+                .push((task.clone(), recurring));
+            current = current
+                .checked_add_days(chrono::Days::new(1))
+                .expect("Invalid date calculation");
+        }
     }
 
     // Process each schedule
@@ -78,19 +86,35 @@ pub async fn get_user_schedule(
         let tasks = task_groups
             .remove(&schedule.schedule_date)
             .unwrap_or_default();
+        let (extra_total, extra_completed) = occurrence_counts
+            .remove(&schedule.schedule_date)
+            .unwrap_or_default();
 
         if !tasks.is_empty() || !query.skip_empty {
             response.data.insert(
                 date_str,
                 DayTasks {
-                    total_tasks: schedule.total_tasks,
-                    completed_tasks: schedule.completed_tasks,
-                    tasks: tasks.into_iter().map(Task::from).collect(),
+                    total_tasks: schedule.total_tasks + extra_total,
+                    completed_tasks: schedule.completed_tasks + extra_completed,
+                    tasks: day_tasks(tasks, tz),
                 },
             );
         }
     }
 
+    // Days with only recurring or carried-over overnight tasks have no schedule
+    for (date, tasks) in task_groups {
+        let (total_tasks, completed_tasks) = occurrence_counts.remove(&date).unwrap_or_default();
+        response.data.insert(
+            date.format(DATE_FMT).to_string(),
+            DayTasks {
+                total_tasks,
+                completed_tasks,
+                tasks: day_tasks(tasks, tz),
+            },
+        );
+    }
+
     // Fill in empty days for date ranges
     if let Some(end_date) = end_date {
         let mut current = start_date;
@@ -118,3 +142,92 @@ pub async fn get_user_schedule(
 
     Ok(Json(Some(response)))
 }
+
+/// Loads the schedules between `start_date` and `end_date` (or just `start_date`), along
+/// with every stored task and recurring occurrence (flagged `true`) touching that range
+pub(crate) async fn load_schedule(
+    db: &TasksDb,
+    user_id: Uuid,
+    start_date: NaiveDate,
+    end_date: Option<NaiveDate>,
+    tz: Tz,
+) -> Result<(Vec<DB::Schedule>, Vec<(DB::Task, bool)>)> {
+    let (schedules, tasks) = db.get_user_schedule(user_id, start_date, end_date).await?;
+
+    // Expand recurring tasks, from the day before so overnight occurrences carry over
+    let occurrences = db
+        .get_recurring_occurrences(
+            user_id,
+            start_date.pred_opt().unwrap_or(start_date),
+            occurrence_end(start_date, end_date),
+            tz,
+        )
+        .await?;
+
+    let entries = tasks
+        .into_iter()
+        .map(|task| (task, false))
+        .chain(occurrences.into_iter().map(|task| (task, true)))
+        .filter(|(task, _)| task_days(task, tz).1 >= start_date)
+        .collect();
+
+    Ok((schedules, entries))
+}
+
+/// Orders a day's stored tasks and recurring occurrences by start time
+fn day_tasks(mut tasks: Vec<(DB::Task, bool)>, tz: Tz) -> Vec<Task> {
+    tasks.sort_by_key(|(task, _)| task.start_time);
+    tasks
+        .into_iter()
+        .map(|(task, recurring)| match recurring {
+            true => Task::occurrence(task, tz),
+            false => Task::in_timezone(task, tz),
+        })
+        .collect()
+}
+
+/// First and last day a task occupies in `tz`
+///
+/// A task ending exactly at midnight does not occupy the day it ends on
+fn task_days(task: &DB::Task, tz: Tz) -> (NaiveDate, NaiveDate) {
+    let ends_at_midnight = task.end_time.with_timezone(&tz).time() == NaiveTime::MIN;
+    let last = if ends_at_midnight && task.end_date > task.schedule_date {
+        task.end_date.pred_opt().unwrap_or(task.end_date)
+    } else {
+        task.end_date
+    };
+
+    (task.schedule_date, last)
+}
+
+/// Recomputes a user's schedule counters from their tasks (admin only)
+///
+/// # Endpoint
+/// ```text
+/// POST /v1/user/:user_id/schedule/recompute
+/// ```
+///
+/// # Query Parameters
+/// Same `date`/`until`/`range` parameters as `GET /v1/user/:user_id/schedule`
+pub async fn recompute_schedule(
+    State(db): State<TasksDb>,
+    Path(user_id): Path<Uuid>,
+    Query(query): Query<DateRangeQuery>,
+) -> Result<Json<RecomputeResponse>> {
+    query.validate_all()?;
+
+    let tz = db.get_user_timezone(user_id).await?;
+    let (start_date, end_date) = query.date_bounds(tz)?;
+    let end_date = end_date.unwrap_or(start_date);
+
+    let schedules_updated = db
+        .recompute_schedule_counters(user_id, start_date, end_date)
+        .await?;
+
+    Ok(Json(RecomputeResponse {
+        user_id,
+        start_date: start_date.format(DATE_FMT).to_string(),
+        end_date: end_date.format(DATE_FMT).to_string(),
+        schedules_updated,
+    }))
+}
@@ -1,107 +1,117 @@
-use axum::{extract::Path, Json};
-use chrono::{NaiveDate, NaiveTime};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
 use uuid::Uuid;
 
-use crate::db::TasksDb;
+use crate::db::{BulkOutcome, TasksDb};
 use crate::models::request::UpdateTaskRequest;
 use crate::models::{
-    query::DATE_FMT,
-    request::{CreateTaskRequest, TasksRequest},
-    response::{Task, TasksResponse},
+    query::{TaskCursor, TasksQuery},
+    request::{
+        BulkOperation, BulkTasksRequest, CreateTaskRequest, TasksRequest, MAX_BULK_OPERATIONS,
+    },
+    response::{BulkItemResult, BulkTasksResponse, Task, TasksPage, TasksResponse},
+};
+use common::{
+    error::{Error, Result},
+    models::database as DB,
 };
-use common::error::{Error, Result};
 
-// GET /v1/tasks - Get all tasks
-pub async fn get_all_tasks() -> Result<Json<Vec<TasksResponse>>> {
-    let db = TasksDb::new().await?;
-    let tasks = db.get_all_tasks().await?;
+/// Lists tasks across users, a page at a time
+///
+/// # Endpoint
+/// ```text
+/// GET /v1/tasks
+/// ```
+///
+/// # Query Parameters
+/// - `limit`: Optional page size (1-500), defaults to 100
+/// - `cursor`: Optional `next_cursor` of the previous page
+/// - `from`, `until`: Optional YYYY-MM-DD bounds on the days tasks touch
+/// - `category`, `completed`, `user_id`: Optional exact-match filters
+///
+/// Tasks are ordered by user, then start, so each user's tasks form one group
+pub async fn get_all_tasks(
+    State(db): State<TasksDb>,
+    Query(query): Query<TasksQuery>,
+) -> Result<Json<TasksPage>> {
+    let filter = query.filter()?;
 
-    let mut user_tasks_map = std::collections::HashMap::new();
-    for task in tasks {
-        user_tasks_map
-            .entry(task.user_id)
-            .or_insert_with(Vec::new)
-            .push(Task::from(task));
-    }
+    let mut tasks = db.get_tasks_page(&filter).await?;
 
-    let response = user_tasks_map
-        .into_iter()
-        .map(|(user_id, all_tasks)| TasksResponse { user_id, all_tasks })
-        .collect();
+    // The extra task fetched past the limit means there's another page
+    let next_cursor = if tasks.len() as i64 > filter.limit {
+        tasks.truncate(filter.limit as usize);
+        tasks.last().map(|task| {
+            TaskCursor {
+                user_id: task.user_id,
+                schedule_date: task.schedule_date,
+                start_time: task.start_time,
+                id: task.id,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
 
-    Ok(Json(response))
+    Ok(Json(TasksPage {
+        users: group_by_user(tasks),
+        next_cursor,
+    }))
 }
 
 // POST /v1/tasks/batch - Get tasks for specific users
 pub async fn get_tasks_batch(
+    State(db): State<TasksDb>,
     Json(payload): Json<TasksRequest>,
 ) -> Result<Json<Vec<TasksResponse>>> {
     if payload.user_ids.is_empty() {
         return Err(Error::validation("At least one user ID must be provided"));
     }
 
-    let db = TasksDb::new().await?;
     let tasks = db.get_users_tasks(&payload.user_ids).await?;
 
-    let mut user_tasks_map = std::collections::HashMap::new();
+    Ok(Json(group_by_user(tasks)))
+}
+
+/// Groups tasks ordered by user into one entry per user, keeping that order
+fn group_by_user(tasks: Vec<DB::Task>) -> Vec<TasksResponse> {
+    let mut groups: Vec<TasksResponse> = Vec::new();
     for task in tasks {
-        user_tasks_map
-            .entry(task.user_id)
-            .or_insert_with(Vec::new)
-            .push(Task::from(task));
+        match groups.last_mut() {
+            Some(group) if group.user_id == task.user_id => group.all_tasks.push(Task::from(task)),
+            _ => groups.push(TasksResponse {
+                user_id: task.user_id,
+                all_tasks: vec![Task::from(task)],
+            }),
+        }
     }
-
-    let response = user_tasks_map
-        .into_iter()
-        .map(|(user_id, all_tasks)| TasksResponse { user_id, all_tasks })
-        .collect();
-
-    Ok(Json(response))
+    groups
 }
 
 // POST /v1/user/:user_id/tasks - Create task
+// Overlapping tasks are rejected with 409 unless `allow_overlap` is set
 pub async fn create_task(
+    State(db): State<TasksDb>,
     Path(user_id): Path<Uuid>,
     Json(payload): Json<CreateTaskRequest>,
 ) -> Result<Json<Task>> {
     // Validates payload's data structure
     payload.validate_all()?;
 
-    // Parse date
-    let date = NaiveDate::parse_from_str(&payload.date, DATE_FMT)
-        .map_err(|_| Error::validation("Invalid date format"))?;
-
-    if !payload.start_time.contains(':') || !payload.end_time.contains(':') {
-        return Err(Error::validation("Time must be in HH:MM format"));
-    }
-
-    let start_time = NaiveTime::parse_from_str(&payload.start_time, "%H:%M")
-        .map_err(|_| Error::validation("Start time must be in HH:MM format"))?;
-    let end_time = NaiveTime::parse_from_str(&payload.end_time, "%H:%M")
-        .map_err(|_| Error::validation("End time must be in HH:MM format"))?;
+    let tz = db.get_user_timezone(user_id).await?;
+    let task = db.add_task(user_id, &payload, tz).await?;
 
-    // Validate times
-    if end_time <= start_time {
-        return Err(Error::validation("End time must be after start time"));
-    }
-
-    let db = TasksDb::new().await?;
-    let task = db
-        .add_task(
-            user_id,
-            date,
-            &payload.name,
-            &payload.category,
-            &payload.start_time,
-            &payload.end_time,
-        )
-        .await?;
-
-    Ok(Json(Task::from(task)))
+    Ok(Json(Task::in_timezone(task, tz)))
 }
 
 // PATCH /v1/user/:user_id/tasks/:task_id
+// Moving a task onto another one is rejected with 409 unless `allow_overlap` is set
 pub async fn update_task(
+    State(db): State<TasksDb>,
     Path((user_id, task_id)): Path<(Uuid, Uuid)>,
     Json(payload): Json<UpdateTaskRequest>,
 ) -> Result<Json<Task>> {
@@ -111,30 +121,154 @@ pub async fn update_task(
 
     payload.validate_all()?;
 
-    let date = payload.date.as_deref().map(|date_str| {
-        NaiveDate::parse_from_str(date_str, DATE_FMT).expect("Date format already validated")
-    });
-
-    let db = TasksDb::new().await?;
-    let task = db
-        .update_task(
-            user_id,
-            task_id,
-            payload.name.as_deref(),
-            payload.category.as_deref(),
-            payload.start_time.as_deref(),
-            payload.end_time.as_deref(),
-            payload.completed,
-            date,
-        )
-        .await?;
-
-    Ok(Json(Task::from(task)))
+    let tz = db.get_user_timezone(user_id).await?;
+    let task = db.update_task(user_id, task_id, &payload, tz).await?;
+
+    Ok(Json(Task::in_timezone(task, tz)))
 }
 
 // DELETE /v1/user/:user_id/tasks/:task_id
-pub async fn delete_task(Path((user_id, task_id)): Path<(Uuid, Uuid)>) -> Result<()> {
-    let db = TasksDb::new().await?;
+pub async fn delete_task(
+    State(db): State<TasksDb>,
+    Path((user_id, task_id)): Path<(Uuid, Uuid)>,
+) -> Result<()> {
     db.delete_task(user_id, task_id).await?;
     Ok(())
 }
+
+/// Creates, updates and deletes a user's tasks in one atomic request
+///
+/// # Endpoint
+/// ```text
+/// POST /v1/user/:user_id/tasks/bulk
+/// ```
+///
+/// # Request Body
+/// `operations`: up to 100 items tagged by `op`, each using the fields of its
+/// single-task endpoint:
+/// ```json
+/// {
+///     "operations": [
+///         { "op": "create", "name": "Gym", "category": "health", "date": "2024-11-25", "start_time": "07:00", "end_time": "08:00" },
+///         { "op": "update", "task_id": "...", "completed": true },
+///         { "op": "delete", "task_id": "..." }
+///     ]
+/// }
+/// ```
+///
+/// Either every operation is applied (200) or none is: the response then carries
+/// the failing item's status, and the other items report 424
+pub async fn bulk_tasks(
+    State(db): State<TasksDb>,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<BulkTasksRequest>,
+) -> Result<(StatusCode, Json<BulkTasksResponse>)> {
+    let operations = payload.operations;
+    if operations.is_empty() {
+        return Err(Error::validation("At least one operation must be provided"));
+    }
+    if operations.len() > MAX_BULK_OPERATIONS {
+        return Err(Error::validation(format!(
+            "At most {} operations can be sent at once",
+            MAX_BULK_OPERATIONS
+        )));
+    }
+
+    // Validate everything up front so an invalid item never reaches the database
+    let validation: Vec<Result<()>> = operations.iter().map(|op| op.validate_all()).collect();
+    if validation.iter().any(|result| result.is_err()) {
+        let results = operations
+            .iter()
+            .zip(validation)
+            .enumerate()
+            .map(|(index, (op, result))| match result {
+                Ok(()) => not_applied(index, op, "Not applied: another operation is invalid"),
+                Err(error) => failed(index, op, &error),
+            })
+            .collect();
+
+        return Ok(bulk_response(StatusCode::BAD_REQUEST, results));
+    }
+
+    let tz = db.get_user_timezone(user_id).await?;
+
+    match db.apply_bulk(user_id, &operations, tz).await? {
+        BulkOutcome::Applied(tasks) => {
+            let results = operations
+                .iter()
+                .zip(tasks)
+                .enumerate()
+                .map(|(index, (op, task))| BulkItemResult {
+                    index,
+                    op: op.name().to_string(),
+                    status: StatusCode::OK.as_u16(),
+                    task: task.map(|task| Task::in_timezone(task, tz)),
+                    error: None,
+                    conflicting_task_ids: None,
+                })
+                .collect();
+
+            Ok((
+                StatusCode::OK,
+                Json(BulkTasksResponse {
+                    applied: true,
+                    results,
+                }),
+            ))
+        }
+        BulkOutcome::Failed { index, error } => {
+            let reason = format!("Not applied: operation {} failed", index);
+            let results = operations
+                .iter()
+                .enumerate()
+                .map(|(i, op)| match i == index {
+                    true => failed(i, op, &error),
+                    false => not_applied(i, op, &reason),
+                })
+                .collect();
+
+            Ok(bulk_response(error.status(), results))
+        }
+    }
+}
+
+// Helper function for a rejected batch
+fn bulk_response(
+    status: StatusCode,
+    results: Vec<BulkItemResult>,
+) -> (StatusCode, Json<BulkTasksResponse>) {
+    (
+        status,
+        Json(BulkTasksResponse {
+            applied: false,
+            results,
+        }),
+    )
+}
+
+// Helper function for the operation that caused a batch to be rejected
+fn failed(index: usize, op: &BulkOperation, error: &Error) -> BulkItemResult {
+    BulkItemResult {
+        index,
+        op: op.name().to_string(),
+        status: error.status().as_u16(),
+        task: None,
+        error: Some(error.public_message()),
+        conflicting_task_ids: match error {
+            Error::Overlap(ids) => Some(ids.clone()),
+            _ => None,
+        },
+    }
+}
+
+// Helper function for an operation rolled back (or skipped) because of another one
+fn not_applied(index: usize, op: &BulkOperation, reason: &str) -> BulkItemResult {
+    BulkItemResult {
+        index,
+        op: op.name().to_string(),
+        status: StatusCode::FAILED_DEPENDENCY.as_u16(),
+        task: None,
+        error: Some(reason.to_string()),
+        conflicting_task_ids: None,
+    }
+}
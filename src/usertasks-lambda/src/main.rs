@@ -1,19 +1,41 @@
-use axum::{
-    middleware,
-    routing::{get, patch, post},
-    Router,
-};
+use axum::{http::Method, middleware};
 use lambda_http::{run, Error};
-use std::env::set_var;
+use std::{
+    env::{self, set_var},
+    sync::Arc,
+};
 
-use common::services::{cors::cors_middleware, mw_auth::auth};
-use handlers::schedule::get_user_schedule;
-use handlers::tasks::{create_task, delete_task, get_all_tasks, get_tasks_batch, update_task};
+use common::{
+    database::{create_pool, migrations, DatabaseConfig},
+    routes::{self, ManifestRouter},
+    services::{
+        cors::{cors_middleware, CorsConfig},
+        jwt::JwtVerifier,
+        mw_access::{require_path_user, ADMIN_SCOPE, TASKS_READ_ALL_SCOPE},
+        mw_auth::auth,
+    },
+};
+use db::TasksDb;
+use handlers::calendar::{
+    get_calendar_feed, import_ics, revoke_calendar_token, rotate_calendar_token,
+};
+use handlers::export::export_tasks;
+use handlers::recurring::{
+    create_recurring_task, delete_occurrence, delete_recurring_task, get_recurring_tasks,
+    update_occurrence,
+};
+use handlers::schedule::{get_user_schedule, recompute_schedule};
+use handlers::tasks::{
+    bulk_tasks, create_task, delete_task, get_all_tasks, get_tasks_batch, update_task,
+};
 
 mod db;
 mod handlers;
 mod models;
 
+/// Logical ID of this function in template.yaml
+const SAM_FUNCTION: &str = "UserTasksFunction";
+
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     tracing_subscriber::fmt()
@@ -25,17 +47,132 @@ async fn main() -> Result<(), Error> {
         .json()
         .init();
 
+    let routes = routes();
+    if routes::handle_cli(&routes.manifest(), SAM_FUNCTION)? {
+        return Ok(());
+    }
+
     set_var("AWS_LAMBDA_HTTP_IGNORE_STAGE_IN_PATH", "true");
 
-    let app = Router::new()
-        .route("/v1/user/:user_id/schedule", get(get_user_schedule))
-        .route("/v1/tasks", get(get_all_tasks))
-        .route("/v1/tasks/batch", post(get_tasks_batch))
-        .route("/v1/user/:user_id/tasks", post(create_task))
-        .route("/v1/user/:user_id/tasks/:task_id", patch(update_task))
-        .route("/v1/user/:user_id/tasks/:task_id", post(delete_task))
-        .layer(middleware::from_fn(cors_middleware))
-        .layer(middleware::from_fn(auth));
+    // Created once per cold start so warm invocations reuse open connections
+    let pool = create_pool(DatabaseConfig::new()?).await?;
+
+    // Opt-in so that only one function (or the migrate binary) owns schema changes
+    if env::var("RUN_MIGRATIONS").is_ok_and(|v| v == "true") {
+        migrations::run_migrations(&pool).await?;
+    }
+
+    let verifier = Arc::new(JwtVerifier::from_env()?);
+    let cors = Arc::new(CorsConfig::from_env()?);
+
+    let db = TasksDb::new(pool);
+
+    // Public routes authenticate themselves, so they're merged in outside the auth layers
+    let (authed, public) = routes.into_parts();
+    let app = authed
+        .route_layer(middleware::from_fn(require_path_user))
+        .with_state(db.clone())
+        .layer(middleware::from_fn_with_state(verifier, auth))
+        .merge(public.with_state(db))
+        .layer(middleware::from_fn_with_state(cors, cors_middleware));
 
     run(app).await
 }
+
+/// Every route this function serves; template.yaml must declare the same set
+fn routes() -> ManifestRouter<TasksDb> {
+    ManifestRouter::new()
+        .route(Method::GET, "/v1/user/:user_id/schedule", get_user_schedule)
+        .scoped_route(
+            Method::POST,
+            "/v1/user/:user_id/schedule/recompute",
+            recompute_schedule,
+            ADMIN_SCOPE,
+        )
+        .scoped_route(
+            Method::GET,
+            "/v1/tasks",
+            get_all_tasks,
+            TASKS_READ_ALL_SCOPE,
+        )
+        .scoped_route(
+            Method::POST,
+            "/v1/tasks/batch",
+            get_tasks_batch,
+            TASKS_READ_ALL_SCOPE,
+        )
+        .scoped_route(
+            Method::GET,
+            "/v1/export/tasks.ndjson",
+            export_tasks,
+            TASKS_READ_ALL_SCOPE,
+        )
+        .route(Method::POST, "/v1/user/:user_id/tasks", create_task)
+        .route(Method::POST, "/v1/user/:user_id/tasks/bulk", bulk_tasks)
+        .route(
+            Method::PATCH,
+            "/v1/user/:user_id/tasks/:task_id",
+            update_task,
+        )
+        .route(
+            Method::DELETE,
+            "/v1/user/:user_id/tasks/:task_id",
+            delete_task,
+        )
+        .route(
+            Method::GET,
+            "/v1/user/:user_id/recurring-tasks",
+            get_recurring_tasks,
+        )
+        .route(
+            Method::POST,
+            "/v1/user/:user_id/recurring-tasks",
+            create_recurring_task,
+        )
+        .route(
+            Method::DELETE,
+            "/v1/user/:user_id/recurring-tasks/:recurring_task_id",
+            delete_recurring_task,
+        )
+        .route(
+            Method::PATCH,
+            "/v1/user/:user_id/recurring-tasks/:recurring_task_id/occurrences/:date",
+            update_occurrence,
+        )
+        .route(
+            Method::DELETE,
+            "/v1/user/:user_id/recurring-tasks/:recurring_task_id/occurrences/:date",
+            delete_occurrence,
+        )
+        .route(
+            Method::POST,
+            "/v1/user/:user_id/calendar-token",
+            rotate_calendar_token,
+        )
+        .route(
+            Method::DELETE,
+            "/v1/user/:user_id/calendar-token",
+            revoke_calendar_token,
+        )
+        .route(Method::POST, "/v1/user/:user_id/import/ics", import_ics)
+        .public_route(
+            Method::GET,
+            "/v1/user/:user_id/schedule.ics",
+            get_calendar_feed,
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_match_template() {
+        routes::check_against_template(
+            &routes().manifest(),
+            include_str!("../../../template.yaml"),
+            SAM_FUNCTION,
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,509 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use common::{
+    database::tasks::{MAX_TASK_CATEGORY_CHARS, MAX_TASK_MINUTES, MAX_TASK_NAME_CHARS},
+    error::{Error, Result},
+    models::database as DB,
+};
+
+pub const CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+
+const PRODID: &str = "-//BustleIt//Schedule//EN";
+const UID_DOMAIN: &str = "bustleit";
+const UTC_FMT: &str = "%Y%m%dT%H%M%SZ"; // RFC 5545 UTC date-time
+const MAX_LINE_OCTETS: usize = 75;
+
+/// Most events a single import may contain
+pub const MAX_IMPORT_EVENTS: usize = 1000;
+const IMPORT_CATEGORY: &str = "Calendar"; // for events without CATEGORIES
+
+/// Renders tasks as an RFC 5545 VCALENDAR with one VEVENT per task
+///
+/// Recurring occurrences (flagged `true`) are exported as individual events, keyed by
+/// their recurring task and date, so edits to a single occurrence show up as-is
+pub fn render_calendar(name: &str, tasks: &[(DB::Task, bool)]) -> String {
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, &format!("PRODID:{}", PRODID));
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    push_line(&mut out, "METHOD:PUBLISH");
+    push_line(&mut out, &format!("X-WR-CALNAME:{}", escape_text(name)));
+    // Hint for subscribed calendars on how often to refresh
+    push_line(&mut out, "X-PUBLISHED-TTL:PT1H");
+
+    for (task, recurring) in tasks {
+        push_event(&mut out, task, *recurring);
+    }
+
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+fn push_event(out: &mut String, task: &DB::Task, recurring: bool) {
+    // Stored task IDs are stable; occurrences share their recurring task's ID
+    let uid = match recurring {
+        true => format!(
+            "{}-{}@{}",
+            task.id,
+            task.schedule_date.format("%Y%m%d"),
+            UID_DOMAIN
+        ),
+        false => format!("{}@{}", task.id, UID_DOMAIN),
+    };
+
+    // VEVENT has no completed status, so unfinished tasks are shown as tentative
+    let status = match task.completed {
+        true => "CONFIRMED",
+        false => "TENTATIVE",
+    };
+
+    push_line(out, "BEGIN:VEVENT");
+    push_line(out, &format!("UID:{}", uid));
+    push_line(out, &format!("DTSTAMP:{}", utc(task.updated_at)));
+    push_line(out, &format!("CREATED:{}", utc(task.created_at)));
+    push_line(out, &format!("LAST-MODIFIED:{}", utc(task.updated_at)));
+    push_line(out, &format!("DTSTART:{}", utc(task.start_time)));
+    push_line(out, &format!("DTEND:{}", utc(task.end_time)));
+    push_line(out, &format!("SUMMARY:{}", escape_text(&task.name)));
+    push_line(out, &format!("CATEGORIES:{}", escape_text(&task.category)));
+    push_line(out, &format!("STATUS:{}", status));
+    push_line(out, "TRANSP:OPAQUE");
+    push_line(out, "END:VEVENT");
+}
+
+fn utc(time: DateTime<Utc>) -> String {
+    time.format(UTC_FMT).to_string()
+}
+
+/// Escapes a TEXT value (backslash, semicolon, comma and newlines)
+fn escape_text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Appends a content line, folded at 75 octets without splitting UTF-8 characters
+fn push_line(out: &mut String, line: &str) {
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            // Continuation lines start with a space, which counts towards their length
+            out.push_str("\r\n ");
+            octets = 1;
+        }
+        out.push(c);
+        octets += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// A VEVENT that can be imported as a task
+#[derive(Clone)]
+pub struct ImportedEvent {
+    pub uid: String,
+    pub name: String,
+    pub category: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// Result of parsing one VEVENT of an uploaded calendar
+pub enum ParsedEvent {
+    Event(ImportedEvent),
+    /// Well-formed, but not something that maps onto a task
+    Skipped {
+        uid: Option<String>,
+        reason: String,
+    },
+    Invalid {
+        uid: Option<String>,
+        reason: String,
+    },
+}
+
+/// Parses the VEVENTs of an iCalendar file, in order
+///
+/// Floating times are interpreted in `tz`, and `TZID`s must be IANA names (VTIMEZONE
+/// definitions are ignored). Fails only if the file isn't a VCALENDAR at all
+pub fn parse_events(ics: &str, tz: Tz) -> Result<Vec<ParsedEvent>> {
+    let mut in_calendar = false;
+    let mut components: Vec<String> = Vec::new();
+    let mut properties: Vec<Property> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in unfold(ics) {
+        let Some(property) = Property::parse(&line) else {
+            continue;
+        };
+
+        match property.name.as_str() {
+            "BEGIN" => {
+                let component = property.value.to_ascii_uppercase();
+                in_calendar |= component == "VCALENDAR";
+                if component == "VEVENT" {
+                    properties.clear();
+                }
+                components.push(component);
+            }
+            "END" => {
+                let ended = components.pop();
+                if ended.as_deref() == Some("VEVENT") {
+                    // Stop at the cap rather than parsing the rest of an oversized file
+                    if events.len() == MAX_IMPORT_EVENTS {
+                        return Err(Error::validation(format!(
+                            "Calendar has more than {} events; at most {} can be imported at once",
+                            MAX_IMPORT_EVENTS, MAX_IMPORT_EVENTS
+                        )));
+                    }
+                    events.push(parse_event(&properties, tz));
+                }
+            }
+            // Only the event's own properties, not those of nested VALARMs
+            _ if components.last().map(String::as_str) == Some("VEVENT") => {
+                properties.push(property)
+            }
+            _ => {}
+        }
+    }
+
+    if !in_calendar {
+        return Err(Error::validation("Not an iCalendar (VCALENDAR) file"));
+    }
+
+    Ok(events)
+}
+
+fn parse_event(properties: &[Property], tz: Tz) -> ParsedEvent {
+    let get = |name: &str| properties.iter().find(|p| p.name == name);
+
+    let uid = get("UID").map(|p| p.value.trim().to_string());
+    let skip = |reason: &str| ParsedEvent::Skipped {
+        uid: uid.clone(),
+        reason: reason.to_string(),
+    };
+    let invalid = |reason: String| ParsedEvent::Invalid {
+        uid: uid.clone(),
+        reason,
+    };
+
+    let Some(uid_value) = uid.as_deref().filter(|u| !u.is_empty()) else {
+        return invalid("Missing UID".to_string());
+    };
+    // Re-importing our own feed would duplicate the tasks it was made from
+    if uid_value.ends_with(&format!("@{}", UID_DOMAIN)) {
+        return skip("Event was exported from BustleIt");
+    }
+    if get("STATUS").is_some_and(|p| p.value.eq_ignore_ascii_case("CANCELLED")) {
+        return skip("Event is cancelled");
+    }
+    if ["RRULE", "RDATE", "RECURRENCE-ID"]
+        .iter()
+        .any(|name| get(name).is_some())
+    {
+        return skip("Recurring events are not supported");
+    }
+
+    let Some(dtstart) = get("DTSTART") else {
+        return invalid("Missing DTSTART".to_string());
+    };
+    if dtstart.is_date() {
+        return skip("All-day events are not imported");
+    }
+    let start = match dtstart.date_time(tz) {
+        Ok(start) => start,
+        Err(reason) => return invalid(format!("DTSTART: {}", reason)),
+    };
+
+    let end = if let Some(dtend) = get("DTEND") {
+        match dtend.date_time(tz) {
+            Ok(end) => end,
+            Err(reason) => return invalid(format!("DTEND: {}", reason)),
+        }
+    } else if let Some(duration) = get("DURATION") {
+        match parse_duration(&duration.value).and_then(|d| start.checked_add_signed(d)) {
+            Some(end) => end,
+            None => return invalid(format!("Invalid DURATION '{}'", duration.value)),
+        }
+    } else {
+        return invalid("Event has no DTEND or DURATION".to_string());
+    };
+
+    if end <= start {
+        return invalid("Event must end after it starts".to_string());
+    }
+    if (end - start).num_minutes() > MAX_TASK_MINUTES {
+        return invalid("Event is longer than 7 days".to_string());
+    }
+
+    let name = get("SUMMARY")
+        .map(|p| unescape_text(&p.value).replace('\n', " "))
+        .map(|name| truncate(name.trim(), MAX_TASK_NAME_CHARS))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "Untitled event".to_string());
+    let category = get("CATEGORIES")
+        .and_then(|p| first_list_value(&p.value))
+        .map(|category| truncate(category.trim(), MAX_TASK_CATEGORY_CHARS))
+        .filter(|category| !category.is_empty())
+        .unwrap_or_else(|| IMPORT_CATEGORY.to_string());
+
+    ParsedEvent::Event(ImportedEvent {
+        uid: uid_value.to_string(),
+        name,
+        category,
+        start,
+        end,
+    })
+}
+
+/// A content line: `NAME;PARAM=VALUE:value`
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn parse(line: &str) -> Option<Self> {
+        // The value starts at the first colon outside a quoted parameter value
+        let mut quoted = false;
+        let colon = line.char_indices().find_map(|(i, c)| match c {
+            '"' => {
+                quoted = !quoted;
+                None
+            }
+            ':' if !quoted => Some(i),
+            _ => None,
+        })?;
+
+        let mut parts = split_unquoted(&line[..colon], ';').into_iter();
+        let name = parts.next()?.trim().to_ascii_uppercase();
+        let params = parts
+            .filter_map(|param| {
+                let (key, value) = param.split_once('=')?;
+                Some((
+                    key.trim().to_ascii_uppercase(),
+                    value.trim_matches('"').to_string(),
+                ))
+            })
+            .collect();
+
+        Some(Self {
+            name,
+            params,
+            value: line[colon + 1..].to_string(),
+        })
+    }
+
+    fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn is_date(&self) -> bool {
+        self.param("VALUE")
+            .is_some_and(|v| v.eq_ignore_ascii_case("DATE"))
+            || (self.value.len() == 8 && NaiveDate::parse_from_str(&self.value, "%Y%m%d").is_ok())
+    }
+
+    /// UTC (`Z` suffix), `TZID`-qualified, or floating (in `tz`) date-time
+    fn date_time(&self, tz: Tz) -> std::result::Result<DateTime<Utc>, String> {
+        let value = self.value.trim();
+
+        if let Some(utc) = value.strip_suffix('Z') {
+            return NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+                .map(|time| time.and_utc())
+                .map_err(|_| format!("Invalid date-time '{}'", value));
+        }
+
+        let local = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+            .map_err(|_| format!("Invalid date-time '{}'", value))?;
+        let zone = match self.param("TZID") {
+            // Some clients prefix the ID with a slash to mark it as globally unique
+            Some(tzid) => tzid
+                .trim_start_matches('/')
+                .parse::<Tz>()
+                .map_err(|_| format!("Unknown timezone '{}'", tzid))?,
+            None => tz,
+        };
+
+        zone.from_local_datetime(&local)
+            .earliest()
+            .map(|time| time.with_timezone(&Utc))
+            .ok_or_else(|| format!("{} does not exist in timezone {}", value, zone.name()))
+    }
+}
+
+/// Splits content lines, joining folded continuations
+fn unfold(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in ics.split('\n') {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        match (raw.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ if raw.trim().is_empty() => {}
+            _ => lines.push(raw.to_string()),
+        }
+    }
+    lines
+}
+
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut quoted, mut start) = (false, 0);
+    for (i, c) in value.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if c == separator && !quoted {
+            parts.push(&value[start..i]);
+            start = i + 1;
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// RFC 5545 DURATION, e.g. `PT1H30M` or `P1DT2H`; negative and out-of-range durations
+/// are rejected
+fn parse_duration(value: &str) -> Option<TimeDelta> {
+    let value = value.trim();
+    let value = value.strip_prefix('+').unwrap_or(value);
+    let rest = value.strip_prefix('P')?;
+
+    let (mut seconds, mut number, mut in_time) = (0i64, String::new(), false);
+    for c in rest.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' if number.is_empty() => in_time = true,
+            'W' | 'D' | 'H' | 'M' | 'S' => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                let unit = match (c, in_time) {
+                    ('W', false) => 604_800,
+                    ('D', false) => 86_400,
+                    ('H', true) => 3_600,
+                    ('M', true) => 60,
+                    ('S', true) => 1,
+                    _ => return None,
+                };
+                seconds = seconds.checked_add(n.checked_mul(unit)?)?;
+            }
+            _ => return None,
+        }
+    }
+
+    if !number.is_empty() || rest.is_empty() {
+        return None;
+    }
+    TimeDelta::try_seconds(seconds)
+}
+
+/// Reverses `escape_text`
+fn unescape_text(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => text.push('\n'),
+            Some(other) => text.push(other),
+            None => {}
+        }
+    }
+    text
+}
+
+// First value of a comma separated TEXT list (e.g. CATEGORIES)
+fn first_list_value(value: &str) -> Option<String> {
+    let mut end = value.len();
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ',' => {
+                end = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+    Some(unescape_text(&value[..end])).filter(|v| !v.is_empty())
+}
+
+fn truncate(value: &str, max_chars: usize) -> String {
+    value.chars().take(max_chars).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calendar(events: &[&str]) -> String {
+        let mut ics = String::from("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n");
+        for event in events {
+            ics.push_str("BEGIN:VEVENT\r\n");
+            ics.push_str(event);
+            ics.push_str("END:VEVENT\r\n");
+        }
+        ics.push_str("END:VCALENDAR\r\n");
+        ics
+    }
+
+    #[test]
+    fn parse_duration_reads_units() {
+        assert_eq!(parse_duration("PT1H30M"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_duration("+P1DT2H"), Some(TimeDelta::hours(26)));
+        assert_eq!(parse_duration("P2W"), Some(TimeDelta::weeks(2)));
+        assert_eq!(parse_duration("-PT1H"), None);
+        assert_eq!(parse_duration("P1H"), None);
+    }
+
+    #[test]
+    fn parse_duration_rejects_overflow() {
+        assert_eq!(parse_duration("P9223372036854775807W"), None);
+        assert_eq!(parse_duration("PT9223372036854775807S"), None);
+        assert_eq!(parse_duration("P15250284453W"), None);
+    }
+
+    #[test]
+    fn huge_duration_is_invalid() {
+        let ics = calendar(&["UID:a\r\nDTSTART:20240101T090000Z\r\nDURATION:P15000000000W\r\n"]);
+
+        let events = parse_events(&ics, Tz::UTC).unwrap();
+
+        assert!(matches!(events[..], [ParsedEvent::Invalid { .. }]));
+    }
+
+    #[test]
+    fn parse_events_stops_at_the_cap() {
+        let event = "UID:a\r\nDTSTART:20240101T090000Z\r\nDURATION:PT1H\r\n";
+
+        let at_cap = calendar(&vec![event; MAX_IMPORT_EVENTS]);
+        assert_eq!(
+            parse_events(&at_cap, Tz::UTC).unwrap().len(),
+            MAX_IMPORT_EVENTS
+        );
+
+        let over_cap = calendar(&vec![event; MAX_IMPORT_EVENTS + 1]);
+        assert!(matches!(
+            parse_events(&over_cap, Tz::UTC),
+            Err(Error::Validation(_))
+        ));
+    }
+}
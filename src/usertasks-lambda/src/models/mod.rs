@@ -1,3 +1,4 @@
+pub mod ical;
 pub mod query;
 pub mod request;
 pub mod response;
@@ -1,11 +1,21 @@
-use chrono::NaiveDate;
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use chrono_tz::Tz;
 use serde::Deserialize;
+use uuid::Uuid;
 use validator::{Validate, ValidationError};
 
 use common::error::{Error, Result};
 
 pub const DATE_FMT: &str = "%Y-%m-%d"; // YYYY-MM-DD
 
+pub const DEFAULT_PAGE_SIZE: i64 = 100;
+
+/// Most days of a `DateRangeQuery` that recurring tasks are expanded over
+///
+/// `until` may reach further for stored tasks, but expanding every series across it
+/// would be unbounded work
+pub const MAX_RANGE_DAYS: i64 = 31;
+
 #[derive(Deserialize, Validate)]
 pub struct DateRangeQuery {
     #[validate(custom(function = "validate_date_format", message = "Invalid date format"))]
@@ -21,6 +31,130 @@ pub struct DateRangeQuery {
     pub skip_empty: bool,
 }
 
+#[derive(Deserialize)]
+pub struct FeedQuery {
+    pub token: Option<String>, // Calendar feed token, since calendar apps can't send headers
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    // Import events even if they overlap existing tasks
+    #[serde(default)]
+    pub allow_overlap: bool,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct TasksQuery {
+    #[validate(range(min = 1, max = 500, message = "Limit must be between 1 and 500"))]
+    pub limit: Option<i64>, // Page size, defaults to 100
+
+    pub cursor: Option<String>, // next_cursor of the previous page
+
+    #[validate(custom(function = "validate_date_format", message = "Invalid date format"))]
+    pub from: Option<String>, // Tasks touching this day or later: YYYY-MM-DD
+
+    #[validate(custom(function = "validate_date_format", message = "Invalid date format"))]
+    pub until: Option<String>, // Tasks starting on this day or earlier: YYYY-MM-DD
+
+    #[validate(length(min = 1, max = 100, message = "Invalid category"))]
+    pub category: Option<String>,
+
+    pub completed: Option<bool>,
+
+    pub user_id: Option<Uuid>,
+}
+
+/// Validated `TasksQuery`, as used by `TasksDb::get_tasks_page`
+pub struct TaskFilter {
+    pub from: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+    pub category: Option<String>,
+    pub completed: Option<bool>,
+    pub user_id: Option<Uuid>,
+    pub after: Option<TaskCursor>,
+    pub limit: i64,
+}
+
+/// Position in the (user_id, schedule_date, start_time, id) ordering of tasks
+///
+/// Carries the whole sort key, so paging keeps working if the task it points at is deleted
+pub struct TaskCursor {
+    pub user_id: Uuid,
+    pub schedule_date: NaiveDate,
+    pub start_time: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl TasksQuery {
+    pub fn filter(&self) -> Result<TaskFilter> {
+        if let Err(validation_errors) = self.validate() {
+            return Err(Error::validation(validation_errors.to_string()));
+        }
+
+        let parse_date = |date: &Option<String>| {
+            date.as_deref()
+                .map(|d| NaiveDate::parse_from_str(d, DATE_FMT))
+                .transpose()
+                .map_err(|_| Error::validation("Invalid date format. Expected YYYY-MM-DD"))
+        };
+        let (from, until) = (parse_date(&self.from)?, parse_date(&self.until)?);
+
+        if let (Some(from), Some(until)) = (from, until) {
+            if until < from {
+                return Err(Error::validation("'until' must not be before 'from'"));
+            }
+        }
+
+        Ok(TaskFilter {
+            from,
+            until,
+            category: self.category.clone(),
+            completed: self.completed,
+            user_id: self.user_id,
+            after: self.cursor.as_deref().map(TaskCursor::decode).transpose()?,
+            limit: self.limit.unwrap_or(DEFAULT_PAGE_SIZE),
+        })
+    }
+}
+
+impl TaskCursor {
+    /// Opaque to clients: `<user_id>_<date>_<start micros>_<id>`
+    pub fn encode(&self) -> String {
+        format!(
+            "{}_{}_{}_{}",
+            self.user_id.simple(),
+            self.schedule_date.format("%Y%m%d"),
+            self.start_time.timestamp_micros(),
+            self.id.simple()
+        )
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self> {
+        let invalid = || Error::validation("Invalid cursor");
+        let parts: Vec<&str> = cursor.split('_').collect();
+        let [user_id, date, start, id] = parts[..] else {
+            return Err(invalid());
+        };
+
+        Ok(Self {
+            user_id: Uuid::parse_str(user_id).map_err(|_| invalid())?,
+            schedule_date: NaiveDate::parse_from_str(date, "%Y%m%d").map_err(|_| invalid())?,
+            start_time: start
+                .parse()
+                .ok()
+                .and_then(DateTime::from_timestamp_micros)
+                .ok_or_else(invalid)?,
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    pub since: Option<DateTime<Utc>>, // RFC 3339; only rows updated at or after it
+    pub cursor: Option<String>,       // x-next-cursor of the previous page
+}
+
 /// Checks if date compiles with YYYY-MM-DD format
 fn validate_date_format(date: &str) -> std::result::Result<(), ValidationError> {
     NaiveDate::parse_from_str(date, DATE_FMT)
@@ -37,6 +171,42 @@ impl DateRangeQuery {
         Ok(())
     }
 
+    /// Resolves the start date (today in `tz` if not provided) and optional end date
+    pub fn date_bounds(&self, tz: Tz) -> Result<(NaiveDate, Option<NaiveDate>)> {
+        let start_date = self
+            .date
+            .as_ref()
+            .map(|d| NaiveDate::parse_from_str(d, DATE_FMT))
+            .transpose()
+            .map_err(|_| Error::validation("Invalid start date format"))?
+            .unwrap_or_else(|| Utc::now().with_timezone(&tz).date_naive());
+
+        // Calculate end date if range or until is provided
+        let end_date = if let Some(until) = &self.until {
+            Some(
+                NaiveDate::parse_from_str(until, DATE_FMT)
+                    .map_err(|_| Error::validation("Invalid end date format"))?,
+            )
+        } else if let Some(range) = self.range {
+            Some(
+                start_date
+                    .checked_add_days(chrono::Days::new((range - 1) as u64))
+                    .ok_or_else(|| Error::validation("Invalid date range calculation"))?,
+            )
+        } else {
+            None
+        };
+
+        // Checked here as well, since the start date may be today in `tz`
+        if let Some(end_date) = end_date {
+            if end_date < start_date {
+                return Err(Error::validation("End date must be after start date"));
+            }
+        }
+
+        Ok((start_date, end_date))
+    }
+
     /// Ensures the date ranges are valid
     fn validate_date_range(&self) -> Result<()> {
         // Prevents defining both 'until' & 'range' in the same request
@@ -60,3 +230,60 @@ impl DateRangeQuery {
         Ok(())
     }
 }
+
+/// Last day recurring tasks are expanded to for a schedule from `start_date`, capped at
+/// [`MAX_RANGE_DAYS`] days
+pub fn occurrence_end(start_date: NaiveDate, end_date: Option<NaiveDate>) -> NaiveDate {
+    let last_date = end_date.unwrap_or(start_date);
+    let cap = start_date
+        .checked_add_days(Days::new(MAX_RANGE_DAYS as u64 - 1))
+        .unwrap_or(NaiveDate::MAX);
+
+    last_date.min(cap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(date: Option<&str>, until: &str) -> DateRangeQuery {
+        DateRangeQuery {
+            date: date.map(str::to_string),
+            until: Some(until.to_string()),
+            range: None,
+            skip_empty: false,
+        }
+    }
+
+    #[test]
+    fn date_bounds_allows_long_until() {
+        let (start, end) = query(Some("2024-01-01"), "2024-12-31")
+            .date_bounds(Tz::UTC)
+            .unwrap();
+        assert_eq!(start, NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        assert_eq!(end, NaiveDate::from_ymd_opt(2024, 12, 31));
+
+        // Defaults to today, so an until in the past is rejected
+        let result = query(None, "2000-01-01").date_bounds(Tz::UTC);
+        assert!(matches!(result, Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn occurrence_end_clamps_to_max_range() {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+        assert_eq!(occurrence_end(start, None), start);
+        assert_eq!(
+            occurrence_end(start, NaiveDate::from_ymd_opt(2024, 1, 10)),
+            NaiveDate::from_ymd_opt(2024, 1, 10).unwrap()
+        );
+        assert_eq!(
+            occurrence_end(start, NaiveDate::from_ymd_opt(2024, 12, 31)),
+            NaiveDate::from_ymd_opt(2024, 1, 31).unwrap()
+        );
+        assert_eq!(
+            occurrence_end(NaiveDate::MAX, Some(NaiveDate::MAX)),
+            NaiveDate::MAX
+        );
+    }
+}
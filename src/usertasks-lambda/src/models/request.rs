@@ -1,10 +1,13 @@
-use chrono::NaiveTime;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
 use serde::Deserialize;
 use uuid::Uuid;
 use validator::{Validate, ValidationError};
 
 use crate::models::query::DATE_FMT;
-use common::error::{Error, Result};
+use common::{
+    error::{Error, Result},
+    models::database as DB,
+};
 
 #[derive(Deserialize)]
 pub struct TasksRequest {
@@ -31,10 +34,26 @@ pub struct CreateTaskRequest {
     pub start_time: String,
 
     #[validate(custom(function = "validate_time_format"))]
-    pub end_time: String,
+    pub end_time: Option<String>,
 
     #[validate(custom(function = "validate_date_format"))]
     pub date: String,
+
+    // Day end_time falls on, for tasks that run past midnight (defaults to date)
+    #[validate(custom(function = "validate_date_format"))]
+    pub end_date: Option<String>,
+
+    // Alternative to end_time/end_date
+    #[validate(range(
+        min = 1,
+        max = 10080,
+        message = "Duration must be between 1 minute and 7 days"
+    ))]
+    pub duration_minutes: Option<i64>,
+
+    // Deliberately double-book instead of rejecting overlapping tasks
+    #[serde(default)]
+    pub allow_overlap: bool,
 }
 
 #[derive(Deserialize, Validate)]
@@ -63,6 +82,13 @@ pub struct UpdateTaskRequest {
 
     #[validate(custom(function = "validate_date_format"))]
     pub date: Option<String>,
+
+    #[validate(custom(function = "validate_date_format"))]
+    pub end_date: Option<String>,
+
+    // Deliberately double-book when moving the task
+    #[serde(default)]
+    pub allow_overlap: bool,
 }
 
 // Validates HH:MM time format
@@ -92,19 +118,67 @@ impl CreateTaskRequest {
             return Err(Error::validation(validation_errors.to_string()));
         }
 
-        // Parse and validate times
-        let start_time = NaiveTime::parse_from_str(&self.start_time, "%H:%M")
-            .expect("Time format already validated");
-        let end_time = NaiveTime::parse_from_str(&self.end_time, "%H:%M")
-            .expect("Time format already validated");
+        match (&self.end_time, self.duration_minutes) {
+            (Some(_), Some(_)) => {
+                return Err(Error::validation(
+                    "Specify either end_time or duration_minutes, not both",
+                ))
+            }
+            (None, None) => {
+                return Err(Error::validation(
+                    "Either end_time or duration_minutes is required",
+                ))
+            }
+            (None, Some(_)) if self.end_date.is_some() => {
+                return Err(Error::validation(
+                    "end_date cannot be combined with duration_minutes",
+                ))
+            }
+            _ => {}
+        }
 
         // Check time order
-        if end_time <= start_time {
+        let (start, end) = self.bounds()?;
+        if end <= start {
             return Err(Error::validation("End time must be after start time"));
         }
 
         Ok(())
     }
+
+    /// Resolves the local start and end of the task
+    pub fn bounds(&self) -> Result<(NaiveDateTime, NaiveDateTime)> {
+        let parse_date = |date: &str| {
+            NaiveDate::parse_from_str(date, DATE_FMT)
+                .map_err(|_| Error::validation("Invalid date format. Expected YYYY-MM-DD"))
+        };
+        let parse_time = |time: &str| {
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .map_err(|_| Error::validation("Invalid time format"))
+        };
+
+        let date = parse_date(&self.date)?;
+        let start = date.and_time(parse_time(&self.start_time)?);
+
+        let end = match (&self.end_time, self.duration_minutes) {
+            (Some(end_time), _) => {
+                let end_date = self
+                    .end_date
+                    .as_deref()
+                    .map(parse_date)
+                    .transpose()?
+                    .unwrap_or(date);
+
+                end_date.and_time(parse_time(end_time)?)
+            }
+            (None, Some(minutes)) => start
+                .checked_add_signed(TimeDelta::minutes(minutes))
+                .ok_or_else(|| Error::validation("Task ends outside the supported date range"))?,
+            (None, None) => start,
+        };
+
+        Ok((start, end))
+    }
 }
 
 impl UpdateTaskRequest {
@@ -115,15 +189,10 @@ impl UpdateTaskRequest {
             return Err(Error::validation(validation_errors.to_string()));
         }
 
-        // Check time order if both times are provided
-        if let (Some(start), Some(end)) = (&self.start_time, &self.end_time) {
-            let start_time =
-                NaiveTime::parse_from_str(start, "%H:%M").expect("Time format already validated");
-            let end_time =
-                NaiveTime::parse_from_str(end, "%H:%M").expect("Time format already validated");
-
-            if end_time <= start_time {
-                return Err(Error::validation("End time must be after start time"));
+        // Time order depends on the stored dates, so it is checked against the task itself
+        if let (Some(date), Some(end_date)) = (&self.date, &self.end_date) {
+            if end_date < date {
+                return Err(Error::validation("End date cannot be before date"));
             }
         }
 
@@ -138,5 +207,275 @@ impl UpdateTaskRequest {
             && self.end_time.is_none()
             && self.completed.is_none()
             && self.date.is_none()
+            && self.end_date.is_none()
+    }
+}
+
+/// Upper bound on operations accepted by the bulk endpoint
+pub const MAX_BULK_OPERATIONS: usize = 100;
+
+#[derive(Deserialize)]
+pub struct BulkTasksRequest {
+    pub operations: Vec<BulkOperation>,
+}
+
+/// A single operation of `POST /v1/user/:user_id/tasks/bulk`, tagged by `op`
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BulkOperation {
+    Create(CreateTaskRequest),
+    Update {
+        task_id: Uuid,
+        #[serde(flatten)]
+        changes: UpdateTaskRequest,
+    },
+    Delete {
+        task_id: Uuid,
+    },
+}
+
+impl BulkOperation {
+    /// Name of the operation, as given in `op`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Create(_) => "create",
+            Self::Update { .. } => "update",
+            Self::Delete { .. } => "delete",
+        }
+    }
+
+    /// Validates the operation with the same rules as its single-task endpoint
+    pub fn validate_all(&self) -> Result<()> {
+        match self {
+            Self::Create(payload) => payload.validate_all(),
+            Self::Update { changes, .. } => {
+                if changes.is_empty() {
+                    return Err(Error::validation("No updates provided"));
+                }
+                changes.validate_all()
+            }
+            Self::Delete { .. } => Ok(()),
+        }
+    }
+}
+
+/// RRULE-style weekday codes, indexed by ISO weekday - 1
+pub const WEEKDAY_CODES: [&str; 7] = ["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+
+#[derive(Deserialize, Validate)]
+pub struct CreateRecurringTaskRequest {
+    #[validate(length(
+        min = 1,
+        max = 255,
+        message = "Task name must be between 1 and 255 characters"
+    ))]
+    pub name: String,
+
+    #[validate(length(
+        min = 1,
+        max = 100,
+        message = "Category must be between 1 and 100 characters"
+    ))]
+    pub category: String,
+
+    #[validate(custom(function = "validate_time_format"))]
+    pub start_time: String,
+
+    #[validate(range(
+        min = 1,
+        max = 1440,
+        message = "Duration must be between 1 minute and 24 hours"
+    ))]
+    pub duration_minutes: i32,
+
+    #[validate(custom(function = "validate_date_format"))]
+    pub start_date: String, // First occurrence: YYYY-MM-DD
+
+    pub frequency: String, // daily | weekly
+
+    #[validate(range(min = 1, max = 52, message = "Interval must be between 1 and 52"))]
+    pub interval: Option<i32>, // Every N days/weeks, defaults to 1
+
+    pub by_weekday: Option<Vec<String>>, // Weekly only, e.g. ["MO", "WE", "FR"]
+
+    #[validate(custom(function = "validate_date_format"))]
+    pub until: Option<String>, // Last possible occurrence: YYYY-MM-DD
+
+    #[validate(range(min = 1, max = 1000, message = "Count must be between 1 and 1000"))]
+    pub count: Option<i32>, // Total number of occurrences
+}
+
+impl CreateRecurringTaskRequest {
+    /// Validates the entire request including inter-field validations
+    pub fn validate_all(&self) -> Result<()> {
+        // Run validator derive validations
+        if let Err(validation_errors) = self.validate() {
+            return Err(Error::validation(validation_errors.to_string()));
+        }
+
+        if self.frequency != DB::FREQ_DAILY && self.frequency != DB::FREQ_WEEKLY {
+            return Err(Error::validation("Frequency must be 'daily' or 'weekly'"));
+        }
+
+        if self.by_weekday.is_some() && self.frequency != DB::FREQ_WEEKLY {
+            return Err(Error::validation(
+                "by_weekday is only valid for weekly tasks",
+            ));
+        }
+        self.weekdays()?;
+
+        if self.until.is_some() && self.count.is_some() {
+            return Err(Error::validation("Cannot specify both 'until' and 'count'"));
+        }
+
+        if let Some(until) = &self.until {
+            if until < &self.start_date {
+                return Err(Error::validation(
+                    "Until date must not be before start date",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses by_weekday codes into sorted, de-duplicated ISO weekdays
+    pub fn weekdays(&self) -> Result<Vec<i16>> {
+        let mut weekdays = self
+            .by_weekday
+            .iter()
+            .flatten()
+            .map(|code| {
+                WEEKDAY_CODES
+                    .iter()
+                    .position(|c| c.eq_ignore_ascii_case(code))
+                    .map(|i| i as i16 + 1)
+                    .ok_or_else(|| Error::validation(format!("Invalid weekday: {}", code)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        weekdays.sort_unstable();
+        weekdays.dedup();
+        Ok(weekdays)
+    }
+}
+
+/// Which occurrences an edit applies to
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EditScope {
+    /// Only the addressed occurrence
+    #[default]
+    This,
+    /// The addressed occurrence and every one after it
+    Future,
+}
+
+#[derive(Deserialize)]
+pub struct EditScopeQuery {
+    #[serde(default)]
+    pub scope: EditScope,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateOccurrenceRequest {
+    #[serde(default)]
+    pub scope: EditScope,
+
+    #[validate(length(
+        min = 1,
+        max = 255,
+        message = "Task name must be between 1 and 255 characters"
+    ))]
+    pub name: Option<String>,
+
+    #[validate(length(
+        min = 1,
+        max = 100,
+        message = "Category must be between 1 and 100 characters"
+    ))]
+    pub category: Option<String>,
+
+    #[validate(custom(function = "validate_time_format"))]
+    pub start_time: Option<String>,
+
+    #[validate(range(
+        min = 1,
+        max = 1440,
+        message = "Duration must be between 1 minute and 24 hours"
+    ))]
+    pub duration_minutes: Option<i32>,
+
+    pub completed: Option<bool>, // Per-occurrence, so only valid with scope "this"
+}
+
+impl UpdateOccurrenceRequest {
+    /// Validates the entire request including inter-field validations
+    pub fn validate_all(&self) -> Result<()> {
+        // Run validator derive validations
+        if let Err(validation_errors) = self.validate() {
+            return Err(Error::validation(validation_errors.to_string()));
+        }
+
+        if self.scope == EditScope::Future && self.completed.is_some() {
+            return Err(Error::validation(
+                "completed can only be set on a single occurrence",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the request contains no updates
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.category.is_none()
+            && self.start_time.is_none()
+            && self.duration_minutes.is_none()
+            && self.completed.is_none()
+    }
+
+    /// Parses start_time (call after `validate_all`)
+    pub fn parsed_start_time(&self) -> Option<NaiveTime> {
+        self.start_time.as_deref().map(|time| {
+            NaiveTime::parse_from_str(time, "%H:%M").expect("Time format already validated")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_duration(date: &str, start_time: &str, minutes: i64) -> CreateTaskRequest {
+        CreateTaskRequest {
+            name: "Shift".to_string(),
+            category: "Work".to_string(),
+            start_time: start_time.to_string(),
+            end_time: None,
+            date: date.to_string(),
+            end_date: None,
+            duration_minutes: Some(minutes),
+            allow_overlap: false,
+        }
+    }
+
+    #[test]
+    fn bounds_resolves_duration_past_midnight() {
+        let (start, end) = with_duration("2024-11-25", "22:00", 240).bounds().unwrap();
+
+        let date = NaiveDate::from_ymd_opt(2024, 11, 25).unwrap();
+        assert_eq!(start, date.and_hms_opt(22, 0, 0).unwrap());
+        assert_eq!(end, date.succ_opt().unwrap().and_hms_opt(2, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn validate_all_rejects_duration_past_last_date() {
+        let result = with_duration("+262142-12-31", "23:00", 120).validate_all();
+
+        let Err(Error::Validation(msg)) = result else {
+            panic!("expected a validation error");
+        };
+        assert!(msg.contains("supported date range"));
     }
 }
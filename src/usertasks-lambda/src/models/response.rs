@@ -1,7 +1,9 @@
+use chrono_tz::Tz;
 use serde::Serialize;
 use std::collections::BTreeMap;
 use uuid::Uuid;
 
+use super::{query::DATE_FMT, request::WEEKDAY_CODES};
 use common::models::database as DB;
 
 #[derive(Serialize)]
@@ -10,12 +12,51 @@ pub struct TasksResponse {
     pub all_tasks: Vec<Task>,
 }
 
+#[derive(Serialize)]
+pub struct TasksPage {
+    pub users: Vec<TasksResponse>, // ordered by user_id; a user may continue on the next page
+    pub next_cursor: Option<String>, // None on the last page
+}
+
 #[derive(Serialize)]
 pub struct ScheduleResponse {
     pub user_id: String,
     pub data: BTreeMap<String, DayTasks>,
 }
 
+#[derive(Serialize)]
+pub struct RecomputeResponse {
+    pub user_id: Uuid,
+    pub start_date: String,
+    pub end_date: String,
+    pub schedules_updated: u64,
+}
+
+#[derive(Serialize)]
+pub struct CalendarTokenResponse {
+    pub token: String,     // only shown once; rotate to get a new one
+    pub feed_path: String, // subscribe URL path, including the token
+}
+
+#[derive(Serialize)]
+pub struct ImportResponse {
+    pub created: usize,
+    pub skipped: usize, // duplicates, overlaps and events that aren't imported
+    pub invalid: usize,
+    pub events: Vec<ImportEventResult>, // one per VEVENT, in file order
+}
+
+#[derive(Serialize)]
+pub struct ImportEventResult {
+    pub index: usize,
+    pub uid: Option<String>,
+    pub status: String, // created | skipped | invalid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task: Option<Task>,
+}
+
 #[derive(Serialize)]
 pub struct DayTasks {
     pub total_tasks: i32,
@@ -29,28 +70,85 @@ pub struct Task {
     pub name: String,
     pub category: String,
 
-    pub start_time: String,
+    pub date: String,       // Day the task starts (and is counted) on
+    pub end_date: String,   // Later than date for tasks that run past midnight
+    pub start_time: String, // RFC 3339 with the user's UTC offset
     pub end_time: String,
 
     pub completed: bool,
+    pub recurring: bool, // task_id is then the recurring task's ID
     pub created_at: String,
     pub updated_at: String,
 }
 
-// Converts DB::Task format to Response format (move)
-impl From<DB::Task> for Task {
-    fn from(db_task: DB::Task) -> Self {
+#[derive(Serialize)]
+pub struct BulkTasksResponse {
+    pub applied: bool, // false means nothing was written
+    pub results: Vec<BulkItemResult>,
+}
+
+#[derive(Serialize)]
+pub struct BulkItemResult {
+    pub index: usize,
+    pub op: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task: Option<Task>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conflicting_task_ids: Option<Vec<Uuid>>,
+}
+
+#[derive(Serialize)]
+pub struct RecurringTask {
+    pub recurring_task_id: String,
+    pub name: String,
+    pub category: String,
+    pub start_time: String, // HH:MM in the user's timezone
+    pub duration_minutes: i32,
+    pub frequency: String,
+    pub interval: i32,
+    pub by_weekday: Vec<String>,
+    pub start_date: String,
+    pub until: Option<String>,
+    pub count: Option<i32>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Task {
+    /// Converts DB::Task format to Response format with times shown in `tz`
+    pub fn in_timezone(db_task: DB::Task, tz: Tz) -> Self {
         Self {
             task_id: db_task.id.to_string(),
             name: db_task.name,
             category: db_task.category,
-            start_time: db_task.start_time.to_string(),
-            end_time: db_task.end_time.to_string(),
+            date: db_task.schedule_date.format(DATE_FMT).to_string(),
+            end_date: db_task.end_date.format(DATE_FMT).to_string(),
+            start_time: db_task.start_time.with_timezone(&tz).to_rfc3339(),
+            end_time: db_task.end_time.with_timezone(&tz).to_rfc3339(),
             completed: db_task.completed,
+            recurring: false,
             created_at: db_task.created_at.to_string(),
             updated_at: db_task.updated_at.to_string(),
         }
     }
+
+    /// Converts an expanded recurring task occurrence to Response format
+    pub fn occurrence(db_task: DB::Task, tz: Tz) -> Self {
+        Self {
+            recurring: true,
+            ..Self::in_timezone(db_task, tz)
+        }
+    }
+}
+
+// Converts DB::Task format to Response format (move), keeping times in UTC
+impl From<DB::Task> for Task {
+    fn from(db_task: DB::Task) -> Self {
+        Self::in_timezone(db_task, Tz::UTC)
+    }
 }
 
 impl ScheduleResponse {
@@ -61,3 +159,88 @@ impl ScheduleResponse {
         }
     }
 }
+
+// Converts DB::RecurringTask format to Response format (move)
+impl From<DB::RecurringTask> for RecurringTask {
+    fn from(db_task: DB::RecurringTask) -> Self {
+        Self {
+            recurring_task_id: db_task.id.to_string(),
+            name: db_task.name,
+            category: db_task.category,
+            start_time: db_task.start_time.format("%H:%M").to_string(),
+            duration_minutes: db_task.duration_minutes,
+            frequency: db_task.frequency,
+            interval: db_task.interval,
+            by_weekday: db_task
+                .weekdays
+                .iter()
+                .filter_map(|&day| {
+                    (day as usize)
+                        .checked_sub(1)
+                        .and_then(|i| WEEKDAY_CODES.get(i))
+                })
+                .map(|code| code.to_string())
+                .collect(),
+            start_date: db_task.start_date.format(DATE_FMT).to_string(),
+            until: db_task.until.map(|d| d.format(DATE_FMT).to_string()),
+            count: db_task.count,
+            created_at: db_task.created_at.to_string(),
+            updated_at: db_task.updated_at.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use super::*;
+
+    #[test]
+    fn in_timezone_emits_rfc3339_with_offset() {
+        let date = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+        let db_task = DB::Task {
+            id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            schedule_date: date,
+            end_date: date,
+            name: "Run".to_string(),
+            category: "Exercise".to_string(),
+            start_time: Utc.with_ymd_and_hms(2024, 7, 1, 7, 0, 0).unwrap(),
+            end_time: Utc.with_ymd_and_hms(2024, 7, 1, 8, 30, 0).unwrap(),
+            completed: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        let task = Task::in_timezone(db_task, chrono_tz::Europe::Berlin);
+
+        assert_eq!(task.start_time, "2024-07-01T09:00:00+02:00");
+        assert_eq!(task.end_time, "2024-07-01T10:30:00+02:00");
+    }
+
+    #[test]
+    fn recurring_task_skips_out_of_range_weekdays() {
+        let date = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+        let db_task = DB::RecurringTask {
+            id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            name: "Gym".to_string(),
+            category: "Exercise".to_string(),
+            start_time: date.and_hms_opt(7, 0, 0).unwrap().time(),
+            duration_minutes: 60,
+            frequency: DB::FREQ_WEEKLY.to_string(),
+            interval: 1,
+            weekdays: vec![0, 1, 7, 8, -1],
+            start_date: date,
+            until: None,
+            count: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        let task = RecurringTask::from(db_task);
+
+        assert_eq!(task.by_weekday, ["MO", "SU"]);
+    }
+}